@@ -0,0 +1,243 @@
+//! Rewriting whole Parquet files through Arrow record batches.
+
+use std::{io::Write, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use parquet::{
+    arrow::{
+        arrow_reader::{ParquetRecordBatchReaderBuilder, RowSelection, RowSelector},
+        parquet_to_arrow_schema_by_columns, ArrowWriter, ProjectionMask,
+    },
+    file::{
+        reader::{ChunkReader, FileReader},
+        serialized_reader::SerializedFileReader,
+    },
+    schema::types::SchemaDescriptor,
+};
+
+use crate::{
+    copy::InputRowGroup,
+    rewrite::{check_options, rows_for_bytes, split_rows, SharedChunkReader},
+    schema::{map_columns, project, ColumnSource},
+    RewriteOptions, RewriteReport, RowGroupSize,
+};
+
+/// Writes the records of all Parquet files in `readers`, in order, to a single file in `sink`,
+/// reading them as record batches with `ParquetRecordBatchReader` and writing them with
+/// `ArrowWriter`. All inputs must have the same schema.
+///
+/// The projection, filter and row group size of `options` are applied as in the low-level path,
+/// but an explicit schema is not supported. Every column chunk is decoded and encoded again, so
+/// `raw_copy`, `threads` and `level_buffer_capacity` have no effect.
+///
+/// `ArrowWriter` splits row groups larger than the `max_row_group_size` of the writer properties,
+/// which cannot be changed without losing the per-column ones, so this fails if a row group of the
+/// rewritten file would hold more rows than that.
+pub fn concat_arrow<R, W>(
+    readers: Vec<R>,
+    sink: W,
+    options: &RewriteOptions,
+) -> Result<RewriteReport>
+where
+    R: ChunkReader + 'static,
+    W: Write + Send,
+{
+    check_options(options)?;
+
+    if options.schema.is_some() {
+        bail!("An explicit schema is not supported when rewriting through Arrow");
+    }
+
+    let inputs: Vec<_> = readers.into_iter().map(Arc::new).collect();
+    let files = inputs
+        .iter()
+        .map(|chunks| {
+            Ok(SerializedFileReader::new(SharedChunkReader(Arc::clone(
+                chunks,
+            )))?)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let Some(first) = files.first() else {
+        bail!("Expected at least one input file");
+    };
+
+    let file_metadata = first.metadata().file_metadata();
+    let input_schema = file_metadata.schema_descr();
+
+    for (i, file) in files.iter().enumerate().skip(1) {
+        if file.metadata().file_metadata().schema() != input_schema.root_schema() {
+            bail!("Input {i} has a different schema than the first input");
+        }
+    }
+
+    let (projection, sources) = if options.projection.is_empty() {
+        let sources = (0..input_schema.num_columns())
+            .map(ColumnSource::Input)
+            .collect();
+        (ProjectionMask::all(), sources)
+    } else {
+        let output_schema = SchemaDescriptor::new(project(input_schema, &options.projection)?);
+        let sources = map_columns(input_schema, &output_schema)?;
+        let leaves = sources.iter().filter_map(|source| match source {
+            ColumnSource::Input(j) => Some(*j),
+            ColumnSource::Null => None,
+        });
+        (ProjectionMask::leaves(input_schema, leaves), sources)
+    };
+
+    let arrow_schema = Arc::new(parquet_to_arrow_schema_by_columns(
+        input_schema,
+        projection.clone(),
+        file_metadata.key_value_metadata(),
+    )?);
+
+    let mut report = RewriteReport::default();
+
+    // The filter is evaluated with the low-level reader and handed to the Arrow reader as a row
+    // selection per input.
+    let mut row_groups = Vec::new();
+    let mut selections = Vec::with_capacity(files.len());
+
+    for file in &files {
+        let mut selectors = Vec::new();
+
+        for i in 0..file.num_row_groups() {
+            let row_group_reader = file.get_row_group(i)?;
+            let num_rows = usize::try_from(row_group_reader.metadata().num_rows())?;
+
+            let num_kept = match &options.filter {
+                Some(filter) => {
+                    let mask = filter.evaluate(row_group_reader.as_ref(), num_rows, options)?;
+                    push_selectors(&mut selectors, &mask);
+                    mask.iter().filter(|keep| **keep).count()
+                }
+                None => {
+                    selectors.push(RowSelector::select(num_rows));
+                    num_rows
+                }
+            };
+
+            report.rows_dropped += num_rows - num_kept;
+            row_groups.push(InputRowGroup {
+                reader: file,
+                index: i,
+                num_kept,
+                filtered: num_kept < num_rows,
+            });
+        }
+
+        selections.push(RowSelection::from(selectors));
+    }
+
+    let plan: Vec<_> = match options.row_group_size {
+        None => row_groups
+            .iter()
+            .map(|input| input.num_kept)
+            .filter(|size| *size > 0)
+            .collect(),
+        Some(size) => {
+            let rows_per_row_group = match size {
+                RowGroupSize::Rows(rows) => rows,
+                // A target smaller than the average row still holds one row.
+                RowGroupSize::Bytes(bytes) => rows_for_bytes(&row_groups, &sources, bytes).max(1),
+            };
+
+            let num_rows = row_groups.iter().map(|input| input.num_kept).sum();
+            split_rows(num_rows, rows_per_row_group)
+        }
+    };
+
+    let max_row_group_size = options.properties.max_row_group_size();
+
+    if let Some(size) = plan.iter().find(|size| **size > max_row_group_size) {
+        bail!(
+            "Cannot write a row group of {size} rows through Arrow, the writer properties limit \
+             row groups to {max_row_group_size} rows"
+        );
+    }
+
+    let mut writer = ArrowWriter::try_new(
+        sink,
+        arrow_schema,
+        Some(options.properties.as_ref().clone()),
+    )?;
+
+    let mut sizes = plan.into_iter();
+    // Rows left to write to the row group in progress.
+    let mut remaining = 0;
+
+    for (chunks, selection) in inputs.iter().zip(selections) {
+        let batches =
+            ParquetRecordBatchReaderBuilder::try_new(SharedChunkReader(Arc::clone(chunks)))?
+                .with_batch_size(options.batch_size)
+                .with_projection(projection.clone())
+                .with_row_selection(selection)
+                .build()?;
+
+        for batch in batches {
+            let mut batch = batch?;
+
+            if options.verbose {
+                eprintln!("reader: {} rows read", batch.num_rows());
+            }
+
+            while batch.num_rows() > 0 {
+                if remaining == 0 {
+                    remaining = sizes
+                        .next()
+                        .ok_or_else(|| anyhow!("Read more rows than the input row groups hold"))?;
+                }
+
+                let len = batch.num_rows().min(remaining);
+                writer.write(&batch.slice(0, len))?;
+                batch = batch.slice(len, batch.num_rows() - len);
+                remaining -= len;
+
+                if remaining == 0 {
+                    if options.verbose {
+                        eprintln!("writer: flushing the row group");
+                    }
+
+                    writer.flush()?;
+                }
+            }
+        }
+    }
+
+    if remaining > 0 || sizes.next().is_some() {
+        bail!("Read fewer rows than the input row groups hold");
+    }
+
+    let metadata = writer.close()?;
+
+    report.row_groups = metadata.row_groups.len();
+    report.columns = metadata
+        .row_groups
+        .first()
+        .map_or(0, |row_group| row_group.columns.len());
+    report.rows = metadata.num_rows;
+
+    Ok(report)
+}
+
+/// Appends the runs of kept and dropped records of `mask` to `selectors`.
+fn push_selectors(selectors: &mut Vec<RowSelector>, mask: &[bool]) {
+    let mut start = 0;
+
+    while start < mask.len() {
+        let keep = mask[start];
+        let len = mask[start..]
+            .iter()
+            .position(|other| *other != keep)
+            .unwrap_or(mask.len() - start);
+
+        selectors.push(if keep {
+            RowSelector::select(len)
+        } else {
+            RowSelector::skip(len)
+        });
+
+        start += len;
+    }
+}
@@ -0,0 +1,316 @@
+//! Copying columns record by record through the low-level column API.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use parquet::{
+    column::{
+        reader::get_typed_column_reader,
+        writer::{
+            get_typed_column_writer, get_typed_column_writer_mut, ColumnCloseResult, ColumnWriter,
+        },
+    },
+    data_type::DataType,
+    file::reader::FileReader,
+    schema::types::{ColumnDescPtr, ColumnDescriptor},
+};
+
+use crate::{
+    records::{ColumnRecords, RecordReader},
+    with_data_type, RewriteOptions,
+};
+
+/// Counters for the records copied into a single column chunk.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ColumnCopyReport {
+    pub records_read: usize,
+    pub values_read: usize,
+    pub levels_read: usize,
+    pub values_written: usize,
+}
+
+/// A row group of one of the input files, with the records to copy from it.
+pub struct InputRowGroup<'a> {
+    pub reader: &'a dyn FileReader,
+    /// Index of the row group in `reader`.
+    pub index: usize,
+    /// Number of records to copy from the row group.
+    pub num_kept: usize,
+    /// Whether only some records are copied, picked by the mask of the row group in the
+    /// [`RowGroupMasks`] handed to the copier.
+    pub filtered: bool,
+}
+
+/// Masks of the filtered input row groups currently being copied, by their index in the input
+/// row groups. Only the records whose entry is true are copied.
+pub type RowGroupMasks = BTreeMap<usize, Vec<bool>>;
+
+/// Copies the records of one leaf column of the input to the column chunks of the output, reading
+/// through the input row groups in order. Records are handed out regardless of the row group they
+/// were read from, so output row groups can be larger or smaller than input ones.
+pub trait ColumnCopier: Send {
+    /// Copies the next `num_records` records to `column_writer`. Fails if the input runs out.
+    fn copy(
+        &mut self,
+        column_writer: &mut ColumnWriter,
+        num_records: usize,
+        masks: &RowGroupMasks,
+    ) -> Result<ColumnCopyReport>;
+
+    /// Skips input row group `index`, whose column chunk was copied without the copier. Fails
+    /// unless every record before it has been copied and every row group in between has no
+    /// records to copy.
+    fn skip_row_group(&mut self, index: usize, masks: &RowGroupMasks) -> Result<()>;
+
+    /// Checks that every record of the input has been copied.
+    fn finish(&mut self, masks: &RowGroupMasks) -> Result<()>;
+}
+
+/// Returns a copier for leaf column `column`, described by `descr`, of `row_groups`, dispatching
+/// on its physical type.
+pub fn column_copier<'a>(
+    row_groups: &'a [InputRowGroup<'a>],
+    column: usize,
+    descr: ColumnDescPtr,
+    options: &'a RewriteOptions,
+) -> Box<dyn ColumnCopier + 'a> {
+    with_data_type!(descr.physical_type(), T => Box::new(TypedColumnCopier::<T>::new(
+        row_groups, column, descr, options,
+    )))
+}
+
+/// Writes a null, or an empty list, for each of `num_rows` records of a column that has no
+/// counterpart in the input. `descr` describes the column written.
+pub fn write_nulls(
+    column_writer: &mut ColumnWriter,
+    descr: &ColumnDescriptor,
+    num_rows: usize,
+) -> Result<ColumnCopyReport> {
+    let levels = vec![0; num_rows];
+
+    with_data_type!(descr.physical_type(), T => {
+        get_typed_column_writer_mut::<T>(column_writer).write_batch(
+            &[],
+            Some(&levels),
+            Some(&levels),
+        )
+    })?;
+
+    Ok(ColumnCopyReport::default())
+}
+
+/// Closes a column writer that is not part of a row group writer, dispatching on the physical
+/// type of `descr`, the column written.
+pub fn close_column_writer(
+    column_writer: ColumnWriter,
+    descr: &ColumnDescriptor,
+) -> Result<ColumnCloseResult> {
+    Ok(with_data_type!(descr.physical_type(), T => {
+        get_typed_column_writer::<T>(column_writer).close()
+    })?)
+}
+
+struct TypedColumnCopier<'a, T: DataType> {
+    row_groups: &'a [InputRowGroup<'a>],
+    column: usize,
+    descr: ColumnDescPtr,
+    options: &'a RewriteOptions,
+    next_row_group: usize,
+    row_group: Option<RowGroupRecords<T>>,
+    /// Records read but not copied yet, already filtered.
+    pending: ColumnRecords<T>,
+    /// Read counters not yet handed out with a [`ColumnCopyReport`].
+    report: ColumnCopyReport,
+}
+
+/// The records of the column in the row group currently being read.
+struct RowGroupRecords<T: DataType> {
+    index: usize,
+    num_rows: usize,
+    records_read: usize,
+    reader: RecordReader<T>,
+}
+
+impl<'a, T: DataType> TypedColumnCopier<'a, T> {
+    fn new(
+        row_groups: &'a [InputRowGroup<'a>],
+        column: usize,
+        descr: ColumnDescPtr,
+        options: &'a RewriteOptions,
+    ) -> Self {
+        TypedColumnCopier {
+            row_groups,
+            column,
+            pending: ColumnRecords::new(&descr),
+            descr,
+            options,
+            next_row_group: 0,
+            row_group: None,
+            report: ColumnCopyReport::default(),
+        }
+    }
+
+    /// Reads the next records that survive the mask into `pending`, moving on to the next row
+    /// group when the current one is exhausted. Returns `false` at the end of the input.
+    fn fill(&mut self, masks: &RowGroupMasks) -> Result<bool> {
+        loop {
+            if self.row_group.is_some() {
+                if self.read_row_group(masks)? {
+                    return Ok(true);
+                }
+                continue;
+            }
+
+            if self.next_row_group == self.row_groups.len() {
+                return Ok(false);
+            }
+
+            let index = self.next_row_group;
+            self.next_row_group += 1;
+
+            let input = &self.row_groups[index];
+
+            if input.num_kept == 0 {
+                continue;
+            }
+
+            if self.options.verbose {
+                eprintln!(
+                    "reader: reading column {} of row group {index}",
+                    self.column
+                );
+            }
+
+            let row_group_reader = input.reader.get_row_group(input.index)?;
+            let num_rows = usize::try_from(row_group_reader.metadata().num_rows())?;
+            let column_reader =
+                get_typed_column_reader::<T>(row_group_reader.get_column_reader(self.column)?);
+
+            self.row_group = Some(RowGroupRecords {
+                index,
+                num_rows,
+                records_read: 0,
+                reader: RecordReader::new(column_reader, &self.descr, num_rows, self.options),
+            });
+        }
+    }
+
+    /// Reads the next records of the current row group that survive the mask into `pending`.
+    /// Returns `false` and closes the row group once it is exhausted.
+    fn read_row_group(&mut self, masks: &RowGroupMasks) -> Result<bool> {
+        let Some(row_group) = self.row_group.as_mut() else {
+            return Ok(false);
+        };
+
+        loop {
+            let values_read = row_group.reader.values_read();
+            let levels_read = row_group.reader.levels_read();
+
+            let records = row_group.reader.next_records()?;
+
+            self.report.values_read += row_group.reader.values_read() - values_read;
+            self.report.levels_read += row_group.reader.levels_read() - levels_read;
+
+            let Some(mut records) = records else {
+                if row_group.records_read != row_group.num_rows {
+                    bail!(
+                        "Column {} has {} records in row group {} but the row group metadata \
+                         says {}",
+                        self.descr.path(),
+                        row_group.records_read,
+                        row_group.index,
+                        row_group.num_rows
+                    );
+                }
+
+                self.row_group = None;
+                return Ok(false);
+            };
+
+            let first_record = row_group.records_read;
+            row_group.records_read += records.num_records();
+            self.report.records_read += records.num_records();
+
+            if self.row_groups[row_group.index].filtered {
+                let mask = masks
+                    .get(&row_group.index)
+                    .expect("masks of the row groups being copied are evaluated");
+                records.retain_records(|i| mask.get(first_record + i).copied().unwrap_or(false));
+            }
+
+            if !records.is_empty() {
+                self.pending = records;
+                return Ok(true);
+            }
+        }
+    }
+}
+
+impl<T: DataType> ColumnCopier for TypedColumnCopier<'_, T> {
+    fn copy(
+        &mut self,
+        column_writer: &mut ColumnWriter,
+        num_records: usize,
+        masks: &RowGroupMasks,
+    ) -> Result<ColumnCopyReport> {
+        let column_writer = get_typed_column_writer_mut::<T>(column_writer);
+        let mut records_written = 0;
+        let mut values_written = 0;
+
+        while records_written < num_records {
+            if self.pending.is_empty() && !self.fill(masks)? {
+                bail!(
+                    "Column {} ran out of records, {} more were expected",
+                    self.descr.path(),
+                    num_records - records_written
+                );
+            }
+
+            let records = self.pending.split_records(num_records - records_written);
+            let written = records.write(column_writer)?;
+
+            if self.options.verbose {
+                eprintln!("writer: {written} values written");
+            }
+
+            records_written += records.num_records();
+            values_written += written;
+        }
+
+        Ok(ColumnCopyReport {
+            values_written,
+            ..std::mem::take(&mut self.report)
+        })
+    }
+
+    fn skip_row_group(&mut self, index: usize, masks: &RowGroupMasks) -> Result<()> {
+        let records_left = index < self.next_row_group
+            || !self.pending.is_empty()
+            || self.read_row_group(masks)?
+            || self.row_groups[self.next_row_group..index]
+                .iter()
+                .any(|input| input.num_kept > 0);
+
+        if records_left {
+            bail!(
+                "Column {} has records left before row group {index}",
+                self.descr.path()
+            );
+        }
+
+        self.next_row_group = index + 1;
+
+        Ok(())
+    }
+
+    fn finish(&mut self, masks: &RowGroupMasks) -> Result<()> {
+        if !self.pending.is_empty() || self.fill(masks)? {
+            bail!(
+                "Column {} has more records than were copied",
+                self.descr.path()
+            );
+        }
+
+        Ok(())
+    }
+}
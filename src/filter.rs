@@ -0,0 +1,931 @@
+//! Dropping records that do not match a predicate.
+//!
+//! A predicate compares a leaf column, or the length of a list, with a literal:
+//!
+//! ```text
+//! list_element = 'Name 2'
+//! len(names) > 3 and not (score < 0.5 or flag = false)
+//! ```
+//!
+//! Columns are named by their dotted path or by a unique suffix of it. A record matches a column
+//! comparison if any of its values in that column does, `len` counts the elements of the
+//! outermost list at or below the named field.
+
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use anyhow::{anyhow, bail, Error, Result};
+use parquet::{
+    basic::Repetition,
+    column::reader::{get_typed_column_reader, ColumnReader},
+    data_type::{ByteArray, DataType, FixedLenByteArray, Int96},
+    file::reader::RowGroupReader,
+    schema::types::{ColumnDescriptor, SchemaDescriptor},
+};
+
+use crate::{records::RecordReader, with_data_type, RewriteOptions};
+
+/// A parsed predicate over the records of a file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    expr: Expr,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Compare(Operand, Op, Literal),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Column(Vec<String>),
+    Len(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Op {
+    fn matches(self, ordering: Ordering) -> bool {
+        match self {
+            Op::Eq => ordering.is_eq(),
+            Op::Ne => ordering.is_ne(),
+            Op::Lt => ordering.is_lt(),
+            Op::Le => ordering.is_le(),
+            Op::Gt => ordering.is_gt(),
+            Op::Ge => ordering.is_ge(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+}
+
+impl fmt::Display for Literal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Literal::String(s) => write!(f, "'{s}'"),
+            Literal::Integer(i) => write!(f, "{i}"),
+            Literal::Float(x) => write!(f, "{x}"),
+            Literal::Boolean(b) => write!(f, "{b}"),
+        }
+    }
+}
+
+impl FromStr for Predicate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+
+        let expr = parser.parse_or()?;
+
+        if let Some(token) = parser.tokens.get(parser.pos) {
+            bail!("Unexpected {token:?} in predicate {s:?}");
+        }
+
+        Ok(Predicate { expr })
+    }
+}
+
+impl Predicate {
+    /// Evaluates the predicate for each of the `num_rows` records of a row group.
+    pub fn evaluate(
+        &self,
+        row_group_reader: &dyn RowGroupReader,
+        num_rows: usize,
+        options: &RewriteOptions,
+    ) -> Result<Vec<bool>> {
+        evaluate(&self.expr, row_group_reader, num_rows, options)
+    }
+}
+
+fn evaluate(
+    expr: &Expr,
+    row_group_reader: &dyn RowGroupReader,
+    num_rows: usize,
+    options: &RewriteOptions,
+) -> Result<Vec<bool>> {
+    let combine = |a: &Expr, b: &Expr, f: fn(bool, bool) -> bool| -> Result<Vec<bool>> {
+        let a = evaluate(a, row_group_reader, num_rows, options)?;
+        let b = evaluate(b, row_group_reader, num_rows, options)?;
+        Ok(a.into_iter().zip(b).map(|(a, b)| f(a, b)).collect())
+    };
+
+    match expr {
+        Expr::Or(a, b) => combine(a, b, |a, b| a || b),
+        Expr::And(a, b) => combine(a, b, |a, b| a && b),
+        Expr::Not(a) => Ok(evaluate(a, row_group_reader, num_rows, options)?
+            .into_iter()
+            .map(|matches| !matches)
+            .collect()),
+        Expr::Compare(operand, op, literal) => {
+            let schema = row_group_reader.metadata().schema_descr();
+
+            let (column, test) = match operand {
+                Operand::Column(path) => {
+                    (find_column(schema, path)?, RecordTest::Value(*op, literal))
+                }
+                Operand::Len(path) => {
+                    let Literal::Integer(length) = literal else {
+                        bail!(
+                            "len({}) can only be compared with an integer",
+                            path.join(".")
+                        );
+                    };
+
+                    let (column, def_level, rep_level) = find_list(schema, path)?;
+
+                    (
+                        column,
+                        RecordTest::Len {
+                            def_level,
+                            rep_level,
+                            op: *op,
+                            length: *length,
+                        },
+                    )
+                }
+            };
+
+            let column_reader = row_group_reader.get_column_reader(column)?;
+            record_mask(
+                column_reader,
+                &schema.column(column),
+                num_rows,
+                &test,
+                options,
+            )
+        }
+    }
+}
+
+/// Finds the leaf column whose path is `path`, or ends with it if that is unambiguous.
+fn find_column(schema: &SchemaDescriptor, path: &[String]) -> Result<usize> {
+    let parts = |descr: &ColumnDescriptor| descr.path().parts().to_vec();
+
+    if let Some(i) = schema
+        .columns()
+        .iter()
+        .position(|descr| parts(descr) == path)
+    {
+        return Ok(i);
+    }
+
+    let mut candidates = schema
+        .columns()
+        .iter()
+        .enumerate()
+        .filter(|(_, descr)| parts(descr).ends_with(path));
+
+    match (candidates.next(), candidates.next()) {
+        (Some((i, _)), None) => Ok(i),
+        (Some((_, a)), Some((_, b))) => bail!(
+            "Column {} is ambiguous, it could be {} or {}",
+            path.join("."),
+            a.path(),
+            b.path()
+        ),
+        (None, _) => bail!("The schema has no leaf column {}", path.join(".")),
+    }
+}
+
+/// Finds the first leaf column below the field at `path` and the definition and repetition
+/// levels of the outermost repeated field on the way to it, at or below `path`.
+fn find_list(schema: &SchemaDescriptor, path: &[String]) -> Result<(usize, i16, i16)> {
+    let column = schema
+        .columns()
+        .iter()
+        .position(|descr| descr.path().parts().starts_with(path))
+        .ok_or_else(|| anyhow!("The schema has no field {}", path.join(".")))?;
+
+    let mut fields = schema.root_schema().get_fields();
+    let mut def_level = 0;
+    let mut rep_level = 0;
+
+    for (depth, part) in schema.column(column).path().parts().iter().enumerate() {
+        let field = fields
+            .iter()
+            .find(|field| field.name() == part)
+            .expect("the path of a leaf column leads to it");
+
+        match field.get_basic_info().repetition() {
+            Repetition::REQUIRED => {}
+            Repetition::OPTIONAL => def_level += 1,
+            Repetition::REPEATED => {
+                def_level += 1;
+                rep_level += 1;
+
+                if depth + 1 >= path.len() {
+                    return Ok((column, def_level, rep_level));
+                }
+            }
+        }
+
+        if field.is_group() {
+            fields = field.get_fields();
+        }
+    }
+
+    bail!("len({}) needs a list or repeated field", path.join("."))
+}
+
+enum RecordTest<'a> {
+    /// Any value of the record compares to the literal as `Op` says.
+    Value(Op, &'a Literal),
+    /// The number of levels at or above the given levels compares to `length`.
+    Len {
+        def_level: i16,
+        rep_level: i16,
+        op: Op,
+        length: i64,
+    },
+}
+
+fn record_mask(
+    column_reader: ColumnReader,
+    descr: &ColumnDescriptor,
+    num_rows: usize,
+    test: &RecordTest,
+    options: &RewriteOptions,
+) -> Result<Vec<bool>> {
+    with_data_type!(descr.physical_type(), T => {
+        typed_record_mask::<T>(column_reader, descr, num_rows, test, options)
+    })
+}
+
+fn typed_record_mask<T: DataType>(
+    column_reader: ColumnReader,
+    descr: &ColumnDescriptor,
+    num_rows: usize,
+    test: &RecordTest,
+    options: &RewriteOptions,
+) -> Result<Vec<bool>>
+where
+    T::T: AsScalar,
+{
+    let mut reader = RecordReader::new(
+        get_typed_column_reader::<T>(column_reader),
+        descr,
+        num_rows,
+        options,
+    );
+
+    let mut mask = Vec::with_capacity(num_rows);
+
+    while let Some(records) = reader.next_records()? {
+        for record in records.records() {
+            let matches = match *test {
+                RecordTest::Value(op, literal) => {
+                    let mut matches = false;
+
+                    for value in &records.values[record.values] {
+                        let scalar = value.as_scalar().ok_or_else(|| {
+                            anyhow!("Values of column {} cannot be compared", descr.path())
+                        })?;
+
+                        // NaN is not ordered with anything and matches no comparison.
+                        if compare(&scalar, literal, descr)?.is_some_and(|o| op.matches(o)) {
+                            matches = true;
+                            break;
+                        }
+                    }
+
+                    matches
+                }
+                RecordTest::Len {
+                    def_level,
+                    rep_level,
+                    op,
+                    length,
+                } => {
+                    let elements = record
+                        .levels
+                        .filter(|level| {
+                            records.def_level(*level) >= def_level
+                                && records.rep_level(*level) <= rep_level
+                        })
+                        .count();
+
+                    op.matches((elements as i64).cmp(&length))
+                }
+            };
+
+            mask.push(matches);
+        }
+    }
+
+    if mask.len() != num_rows {
+        bail!(
+            "Column {} has {} records but the row group metadata says {num_rows}",
+            descr.path(),
+            mask.len()
+        );
+    }
+
+    Ok(mask)
+}
+
+/// A value of a leaf column in a form that can be compared with a literal.
+pub(crate) enum Scalar<'a> {
+    Boolean(bool),
+    Integer(i64),
+    Float(f64),
+    Bytes(&'a [u8]),
+}
+
+impl fmt::Display for Scalar<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scalar::Boolean(b) => write!(f, "{b}"),
+            Scalar::Integer(i) => write!(f, "{i}"),
+            Scalar::Float(x) => write!(f, "{x}"),
+            Scalar::Bytes(bytes) => write!(f, "{}", String::from_utf8_lossy(bytes)),
+        }
+    }
+}
+
+/// Physical values that can be turned into a [`Scalar`], all but `Int96`.
+pub(crate) trait AsScalar {
+    fn as_scalar(&self) -> Option<Scalar<'_>>;
+}
+
+impl AsScalar for bool {
+    fn as_scalar(&self) -> Option<Scalar<'_>> {
+        Some(Scalar::Boolean(*self))
+    }
+}
+
+impl AsScalar for i32 {
+    fn as_scalar(&self) -> Option<Scalar<'_>> {
+        Some(Scalar::Integer(i64::from(*self)))
+    }
+}
+
+impl AsScalar for i64 {
+    fn as_scalar(&self) -> Option<Scalar<'_>> {
+        Some(Scalar::Integer(*self))
+    }
+}
+
+impl AsScalar for Int96 {
+    fn as_scalar(&self) -> Option<Scalar<'_>> {
+        None
+    }
+}
+
+impl AsScalar for f32 {
+    fn as_scalar(&self) -> Option<Scalar<'_>> {
+        Some(Scalar::Float(f64::from(*self)))
+    }
+}
+
+impl AsScalar for f64 {
+    fn as_scalar(&self) -> Option<Scalar<'_>> {
+        Some(Scalar::Float(*self))
+    }
+}
+
+impl AsScalar for ByteArray {
+    fn as_scalar(&self) -> Option<Scalar<'_>> {
+        Some(Scalar::Bytes(self.data()))
+    }
+}
+
+impl AsScalar for FixedLenByteArray {
+    fn as_scalar(&self) -> Option<Scalar<'_>> {
+        Some(Scalar::Bytes(self.data()))
+    }
+}
+
+fn compare(
+    scalar: &Scalar,
+    literal: &Literal,
+    descr: &ColumnDescriptor,
+) -> Result<Option<Ordering>> {
+    Ok(match (scalar, literal) {
+        (Scalar::Boolean(a), Literal::Boolean(b)) => Some(a.cmp(b)),
+        (Scalar::Integer(a), Literal::Integer(b)) => Some(a.cmp(b)),
+        (Scalar::Integer(a), Literal::Float(b)) => (*a as f64).partial_cmp(b),
+        (Scalar::Float(a), Literal::Integer(b)) => a.partial_cmp(&(*b as f64)),
+        (Scalar::Float(a), Literal::Float(b)) => a.partial_cmp(b),
+        (Scalar::Bytes(a), Literal::String(b)) => Some((*a).cmp(b.as_bytes())),
+        _ => bail!(
+            "Cannot compare {} column {} with {literal}",
+            descr.physical_type(),
+            descr.path()
+        ),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Path(Vec<String>),
+    String(String),
+    Number(String),
+    Op(Op),
+    LeftParen,
+    RightParen,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LeftParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RightParen);
+            }
+            '\'' => {
+                chars.next();
+                let mut string = String::new();
+
+                loop {
+                    match chars.next() {
+                        Some('\'') if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            string.push('\'');
+                        }
+                        Some('\'') => break,
+                        Some(c) => string.push(c),
+                        None => bail!("Unterminated string in predicate {s:?}"),
+                    }
+                }
+
+                tokens.push(Token::String(string));
+            }
+            '=' | '!' | '<' | '>' => {
+                chars.next();
+                let next = chars.peek().copied();
+
+                let op = match (c, next) {
+                    ('=', Some('='))
+                    | ('!', Some('='))
+                    | ('<', Some('=' | '>'))
+                    | ('>', Some('=')) => {
+                        chars.next();
+                        match (c, next) {
+                            ('=', _) => Op::Eq,
+                            ('!', _) | ('<', Some('>')) => Op::Ne,
+                            ('<', _) => Op::Le,
+                            _ => Op::Ge,
+                        }
+                    }
+                    ('=', _) => Op::Eq,
+                    ('<', _) => Op::Lt,
+                    ('>', _) => Op::Gt,
+                    _ => bail!("Expected != in predicate {s:?}"),
+                };
+
+                tokens.push(Token::Op(op));
+            }
+            c if c.is_ascii_digit() || c == '-' => {
+                let mut number = String::new();
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() || c == '.' || c == '-' || c == 'e' || c == 'E' {
+                        number.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut path = vec![String::new()];
+
+                while let Some(&c) = chars.peek() {
+                    if c.is_alphanumeric() || c == '_' {
+                        path.last_mut().expect("path is never empty").push(c);
+                    } else if c == '.' {
+                        path.push(String::new());
+                    } else {
+                        break;
+                    }
+                    chars.next();
+                }
+
+                if path.iter().any(String::is_empty) {
+                    bail!("Invalid column path {} in predicate {s:?}", path.join("."));
+                }
+
+                tokens.push(Token::Path(path));
+            }
+            c => bail!("Unexpected {c:?} in predicate {s:?}"),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of predicate"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Path(path)) if path.len() == 1 && path[0].eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_unary()?;
+
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_unary()?)));
+        }
+
+        if self.peek() == Some(&Token::LeftParen) {
+            self.pos += 1;
+            let expr = self.parse_or()?;
+
+            return match self.next()? {
+                Token::RightParen => Ok(expr),
+                token => bail!("Expected ) but found {token:?}"),
+            };
+        }
+
+        let operand = if self.keyword("len") {
+            match (self.next()?, self.next()?, self.next()?) {
+                (Token::LeftParen, Token::Path(path), Token::RightParen) => Operand::Len(path),
+                _ => bail!("Expected len(path)"),
+            }
+        } else {
+            match self.next()? {
+                Token::Path(path) => Operand::Column(path),
+                token => bail!("Expected a column but found {token:?}"),
+            }
+        };
+
+        let op = match self.next()? {
+            Token::Op(op) => op,
+            token => bail!("Expected a comparison but found {token:?}"),
+        };
+
+        let literal = match self.next()? {
+            Token::String(s) => Literal::String(s),
+            Token::Number(n) => match n.parse() {
+                Ok(i) => Literal::Integer(i),
+                Err(_) => Literal::Float(
+                    n.parse()
+                        .map_err(|_| anyhow!("Invalid number {n} in predicate"))?,
+                ),
+            },
+            Token::Path(path) if path.len() == 1 && path[0].eq_ignore_ascii_case("true") => {
+                Literal::Boolean(true)
+            }
+            Token::Path(path) if path.len() == 1 && path[0].eq_ignore_ascii_case("false") => {
+                Literal::Boolean(false)
+            }
+            token => bail!("Expected a literal but found {token:?}"),
+        };
+
+        Ok(Expr::Compare(operand, op, literal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use bytes::Bytes;
+    use parquet::{
+        data_type::{ByteArrayType, Int32Type},
+        file::{
+            properties::WriterProperties, reader::FileReader,
+            serialized_reader::SerializedFileReader,
+        },
+        schema::types::SchemaDescriptor,
+    };
+
+    use super::*;
+    use crate::{parse_schema, testdata, testdata::RepeatedWriter};
+
+    fn parse(s: &str) -> Expr {
+        s.parse::<Predicate>()
+            .unwrap_or_else(|error| panic!("{s:?} does not parse: {error}"))
+            .expr
+    }
+
+    fn compare(column: &str, op: Op, literal: Literal) -> Expr {
+        Expr::Compare(Operand::Column(vec![column.to_owned()]), op, literal)
+    }
+
+    fn not(expr: Expr) -> Expr {
+        Expr::Not(Box::new(expr))
+    }
+
+    fn and(a: Expr, b: Expr) -> Expr {
+        Expr::And(Box::new(a), Box::new(b))
+    }
+
+    fn or(a: Expr, b: Expr) -> Expr {
+        Expr::Or(Box::new(a), Box::new(b))
+    }
+
+    fn a() -> Expr {
+        compare("a", Op::Eq, Literal::Integer(1))
+    }
+
+    fn b() -> Expr {
+        compare("b", Op::Eq, Literal::Integer(2))
+    }
+
+    fn c() -> Expr {
+        compare("c", Op::Eq, Literal::Integer(3))
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or_and_not_tighter_than_and() {
+        assert_eq!(parse("a = 1 or b = 2 and c = 3"), or(a(), and(b(), c())));
+        assert_eq!(parse("a = 1 and b = 2 or c = 3"), or(and(a(), b()), c()));
+        assert_eq!(parse("not a = 1 and b = 2"), and(not(a()), b()));
+        assert_eq!(parse("not not a = 1"), not(not(a())));
+        assert_eq!(parse("(a = 1 or b = 2) and c = 3"), and(or(a(), b()), c()));
+        assert_eq!(parse("not (a = 1 or b = 2)"), not(or(a(), b())));
+        assert_eq!(
+            parse("a = 1 OR b = 2 AND NOT c = 3"),
+            or(a(), and(b(), not(c())))
+        );
+    }
+
+    #[test]
+    fn operators_have_alternative_spellings() {
+        let op = |s: &str| match parse(&format!("a {s} 1")) {
+            Expr::Compare(_, op, _) => op,
+            expr => panic!("{s} parsed as {expr:?}"),
+        };
+
+        assert_eq!(op("="), Op::Eq);
+        assert_eq!(op("=="), Op::Eq);
+        assert_eq!(op("!="), Op::Ne);
+        assert_eq!(op("<>"), Op::Ne);
+        assert_eq!(op("<"), Op::Lt);
+        assert_eq!(op("<="), Op::Le);
+        assert_eq!(op(">"), Op::Gt);
+        assert_eq!(op(">="), Op::Ge);
+
+        // Operators need no spaces around them.
+        assert_eq!(parse("a<>1"), compare("a", Op::Ne, Literal::Integer(1)));
+    }
+
+    #[test]
+    fn literals_and_operands_are_parsed() {
+        assert_eq!(
+            parse("a = 'it''s'"),
+            compare("a", Op::Eq, Literal::String("it's".to_owned()))
+        );
+        assert_eq!(
+            parse("a = ''"),
+            compare("a", Op::Eq, Literal::String(String::new()))
+        );
+        assert_eq!(parse("a = -3"), compare("a", Op::Eq, Literal::Integer(-3)));
+        assert_eq!(parse("a = 0.5"), compare("a", Op::Eq, Literal::Float(0.5)));
+        assert_eq!(
+            parse("a = 1e3"),
+            compare("a", Op::Eq, Literal::Float(1000.0))
+        );
+        assert_eq!(
+            parse("a = TRUE"),
+            compare("a", Op::Eq, Literal::Boolean(true))
+        );
+        assert_eq!(
+            parse("a = false"),
+            compare("a", Op::Eq, Literal::Boolean(false))
+        );
+        assert_eq!(
+            parse("len(names.list) >= 2"),
+            Expr::Compare(
+                Operand::Len(vec!["names".to_owned(), "list".to_owned()]),
+                Op::Ge,
+                Literal::Integer(2)
+            )
+        );
+    }
+
+    #[test]
+    fn malformed_predicates_are_rejected() {
+        for s in [
+            "",
+            "a",
+            "a =",
+            "= 1",
+            "a = 'x",
+            "a ! 1",
+            "a = 1 b = 2",
+            "a = 1 and",
+            "(a = 1",
+            "a = 1)",
+            "a..b = 1",
+            "a = b",
+            "a = 1.2.3",
+            "len a = 1",
+            "len(a = 1",
+            "a = 1 # comment",
+        ] {
+            assert!(s.parse::<Predicate>().is_err(), "{s:?} parses");
+        }
+    }
+
+    /// Evaluates `predicate` on the only row group of `bytes`.
+    fn mask(bytes: Bytes, predicate: &str) -> Result<Vec<bool>> {
+        let reader = SerializedFileReader::new(bytes)?;
+        let row_group_reader = reader.get_row_group(0)?;
+        let num_rows = usize::try_from(row_group_reader.metadata().num_rows())?;
+        let options = RewriteOptions::new(Arc::new(WriterProperties::builder().build()));
+
+        predicate
+            .parse::<Predicate>()?
+            .evaluate(row_group_reader.as_ref(), num_rows, &options)
+    }
+
+    fn nullable_names() -> Result<Bytes> {
+        let schema = Arc::new(parse_schema(testdata::NULLABLE_NAMES_SCHEMA));
+        let mut repeated_writer =
+            RepeatedWriter::<ByteArrayType>::for_column(&SchemaDescriptor::new(schema.clone()), 0);
+
+        repeated_writer.push_value(None::<Vec<Option<&str>>>)?;
+        repeated_writer.push_value(Some(Vec::<Option<&str>>::new()))?;
+        repeated_writer.push_value(Some(vec![Some("a"), None, Some("b")]))?;
+        repeated_writer.push_value(Some(vec![Some("a")]))?;
+
+        testdata::create_parquet_file(
+            schema,
+            Arc::new(WriterProperties::builder().build()),
+            &repeated_writer,
+        )
+    }
+
+    #[test]
+    fn len_counts_null_elements_but_not_null_lists() -> Result<()> {
+        let bytes = nullable_names()?;
+
+        assert_eq!(
+            mask(bytes.clone(), "len(names) = 0")?,
+            [true, true, false, false]
+        );
+        assert_eq!(
+            mask(bytes.clone(), "len(names) = 3")?,
+            [false, false, true, false]
+        );
+        assert_eq!(
+            mask(bytes.clone(), "len(names.list) > 0")?,
+            [false, false, true, true]
+        );
+        assert!(mask(bytes, "len(names) = 'a'").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn a_record_matches_if_any_of_its_values_does() -> Result<()> {
+        let bytes = nullable_names()?;
+
+        assert_eq!(
+            mask(bytes.clone(), "list_element = 'a'")?,
+            [false, false, true, true]
+        );
+        assert_eq!(
+            mask(bytes.clone(), "list_element <> 'a'")?,
+            [false, false, true, false]
+        );
+        assert_eq!(
+            mask(bytes.clone(), "not list_element = 'a'")?,
+            [true, true, false, false]
+        );
+        assert_eq!(
+            mask(
+                bytes.clone(),
+                "names.list.list_element > 'a' or len(names) = 1"
+            )?,
+            [false, false, true, true]
+        );
+        assert!(mask(bytes.clone(), "list_element = 1").is_err());
+        assert!(mask(bytes, "missing = 1").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn len_counts_the_outermost_list_below_the_field() -> Result<()> {
+        let schema = Arc::new(parse_schema(
+            "
+            message schema {
+                REQUIRED GROUP matrix (LIST) {
+                    REPEATED GROUP list {
+                        REQUIRED GROUP row (LIST) {
+                            REPEATED GROUP list {
+                                REQUIRED INT32 cell;
+                            }
+                        }
+                    }
+                }
+            }
+            ",
+        ));
+        let mut repeated_writer =
+            RepeatedWriter::<Int32Type>::for_column(&SchemaDescriptor::new(schema.clone()), 0);
+
+        repeated_writer.push_value(vec![vec![1, 2, 3], vec![4]])?;
+        repeated_writer.push_value(Vec::<Vec<i32>>::new())?;
+        repeated_writer.push_value(vec![Vec::<i32>::new()])?;
+        repeated_writer.push_value(vec![vec![5], vec![6], vec![7]])?;
+
+        let bytes = testdata::create_parquet_file(
+            schema,
+            Arc::new(WriterProperties::builder().build()),
+            &repeated_writer,
+        )?;
+
+        assert_eq!(
+            mask(bytes.clone(), "len(matrix) = 2")?,
+            [true, false, false, false]
+        );
+        assert_eq!(
+            mask(bytes.clone(), "len(matrix) = 1")?,
+            [false, false, true, false]
+        );
+        assert_eq!(
+            mask(bytes.clone(), "len(matrix.list.row) = 3")?,
+            [false, false, false, true]
+        );
+        assert_eq!(
+            mask(bytes.clone(), "len(matrix.list.row) = 0")?,
+            [false, true, true, false]
+        );
+        assert_eq!(
+            mask(bytes.clone(), "len(matrix.list.row) = 4")?,
+            [true, false, false, false]
+        );
+        assert_eq!(
+            mask(bytes, "cell >= 4 and cell < 6")?,
+            [true, false, false, true]
+        );
+
+        Ok(())
+    }
+}
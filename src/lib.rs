@@ -0,0 +1,76 @@
+//! Reading Parquet files with `read_records` and writing them back with `write_batch`.
+
+mod arrow;
+mod copy;
+mod filter;
+mod properties;
+mod records;
+mod rewrite;
+mod schema;
+mod source;
+mod split;
+pub mod testdata;
+mod verify;
+
+pub use arrow::concat_arrow;
+pub use filter::Predicate;
+pub use properties::{
+    chunk_matches_properties, preserved_properties, with_compression_overrides, CompressionOverride,
+};
+pub use rewrite::{
+    concat, rewrite, RewriteMode, RewriteOptions, RewriteReport, RowGroupSize, DEFAULT_BATCH_SIZE,
+};
+pub use schema::{map_columns, output_schema, project, ColumnSelector, ColumnSource};
+pub use source::FileChunkReader;
+pub use split::{split, SplitBy, SplitReport, MAX_PARTITIONS};
+pub use verify::{verify, Divergence, VerifyReport};
+
+use parquet::schema::parser::parse_message_type;
+
+/// Evaluates `$body` with `$T` naming the [`DataType`](parquet::data_type::DataType) of the
+/// physical type `$physical_type`, so that code generic over the data type can be called for a
+/// column whose type is only known at runtime.
+macro_rules! with_data_type {
+    ($physical_type:expr, $T:ident => $body:expr) => {
+        match $physical_type {
+            ::parquet::basic::Type::BOOLEAN => {
+                type $T = ::parquet::data_type::BoolType;
+                $body
+            }
+            ::parquet::basic::Type::INT32 => {
+                type $T = ::parquet::data_type::Int32Type;
+                $body
+            }
+            ::parquet::basic::Type::INT64 => {
+                type $T = ::parquet::data_type::Int64Type;
+                $body
+            }
+            ::parquet::basic::Type::INT96 => {
+                type $T = ::parquet::data_type::Int96Type;
+                $body
+            }
+            ::parquet::basic::Type::FLOAT => {
+                type $T = ::parquet::data_type::FloatType;
+                $body
+            }
+            ::parquet::basic::Type::DOUBLE => {
+                type $T = ::parquet::data_type::DoubleType;
+                $body
+            }
+            ::parquet::basic::Type::BYTE_ARRAY => {
+                type $T = ::parquet::data_type::ByteArrayType;
+                $body
+            }
+            ::parquet::basic::Type::FIXED_LEN_BYTE_ARRAY => {
+                type $T = ::parquet::data_type::FixedLenByteArrayType;
+                $body
+            }
+        }
+    };
+}
+
+pub(crate) use with_data_type;
+
+pub fn parse_schema(schema: &str) -> parquet::schema::types::Type {
+    parse_message_type(schema).expect("Bad schema")
+}
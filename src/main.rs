@@ -1,210 +1,474 @@
-use std::sync::Arc;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-use anyhow::Result;
-use bytes::{BufMut, Bytes, BytesMut};
+use anyhow::{bail, Context, Result};
+use bytes::Bytes;
+use clap::{builder::RangedU64ValueParser, Args, Parser, Subcommand};
 use parquet::{
     basic::Compression,
-    data_type::ByteArrayType,
-    file::{
-        properties::WriterProperties, reader::FileReader, serialized_reader::SerializedFileReader,
-        writer::SerializedFileWriter,
+    file::{footer::parse_metadata, metadata::ParquetMetaData, properties::WriterProperties},
+    schema::{
+        parser::parse_message_type,
+        types::{ColumnPath, SchemaDescriptor, TypePtr},
     },
-    schema::parser::parse_message_type,
 };
+use parquet_bug::{
+    concat, output_schema, preserved_properties, rewrite, split, testdata, verify,
+    with_compression_overrides, ColumnSelector, CompressionOverride, FileChunkReader, Predicate,
+    RewriteMode, RewriteOptions, RewriteReport, RowGroupSize, SplitBy, VerifyReport,
+    DEFAULT_BATCH_SIZE,
+};
+
+#[derive(Debug, Parser)]
+#[command(about = "Read Parquet files with read_records and write them back with write_batch")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Rewrite a small generated file in memory (the default)
+    Repro,
+    /// Rewrite a Parquet file from disk
+    Rewrite(RewriteArgs),
+    /// Write the row groups of several Parquet files with the same schema to one file
+    Concat(ConcatArgs),
+    /// Split a Parquet file into several, by row count or into Hive-style partitions
+    Split(SplitArgs),
+    /// Compare two Parquet files record by record
+    Verify {
+        /// The original file
+        expected: PathBuf,
+        /// The file to check against it
+        actual: PathBuf,
+    },
+}
+
+#[derive(Debug, Args)]
+struct RewriteArgs {
+    /// Parquet file to read
+    input: PathBuf,
+    /// Where to write the rewritten file, `-` or nothing for stdout
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    write: WriteArgs,
+    /// Compare the rewritten file with the input afterwards
+    #[arg(long, conflicts_with_all = ["filter", "columns", "schema", "arrow"])]
+    verify: bool,
+}
 
-pub fn parse_schema(schema: &str) -> parquet::schema::types::Type {
-    parse_message_type(schema).expect("Bad schema")
+#[derive(Debug, Args)]
+struct ConcatArgs {
+    /// Parquet files to read, in order
+    #[arg(required = true)]
+    inputs: Vec<PathBuf>,
+    /// Where to write the concatenated file, `-` or nothing for stdout
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    write: WriteArgs,
+}
+
+#[derive(Debug, Args)]
+struct SplitArgs {
+    /// Parquet file to read
+    input: PathBuf,
+    /// Directory to write the parts to
+    directory: PathBuf,
+    /// Write a part every this many rows
+    #[arg(
+        long,
+        value_name = "ROWS",
+        required_unless_present = "by",
+        value_parser = at_least_one()
+    )]
+    rows: Option<usize>,
+    /// Write a COLUMN=VALUE directory for each value of a leaf column, by dotted path
+    #[arg(long, value_name = "COLUMN", conflicts_with = "rows")]
+    by: Option<String>,
+    #[command(flatten)]
+    common: CommonArgs,
+}
+
+/// How records are read and written, shared by every command writing files.
+#[derive(Debug, Args)]
+struct CommonArgs {
+    /// Records per read_records call, more while a record does not fit the level buffers
+    #[arg(long, default_value_t = DEFAULT_BATCH_SIZE, value_parser = at_least_one())]
+    batch_size: usize,
+    /// Initial capacity of the level buffers, grown when a record does not fit
+    #[arg(long, default_value_t = DEFAULT_BATCH_SIZE)]
+    level_buffer_capacity: usize,
+    /// Write with the compression, encodings, statistics and created_by of the input instead of
+    /// the defaults
+    #[arg(long)]
+    preserve: bool,
+    /// Print every read and write call to stderr
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+/// How the output file is written, shared by `rewrite` and `concat`.
+#[derive(Debug, Args)]
+struct WriteArgs {
+    #[command(flatten)]
+    common: CommonArgs,
+    /// File with the message type to write instead of the schema of the input. Columns are
+    /// matched by path, missing ones are dropped and new nullable ones filled with nulls
+    #[arg(long)]
+    schema: Option<PathBuf>,
+    /// Leaf columns to keep, by index or dotted path, separated by commas or repeated
+    #[arg(long = "column", value_name = "COLUMN", value_delimiter = ',')]
+    columns: Vec<ColumnSelector>,
+    /// Compression of a single column as COLUMN=CODEC, e.g. names.list.list_element=ZSTD(3),
+    /// can be repeated
+    #[arg(long = "column-compression", value_name = "COLUMN=CODEC")]
+    column_compression: Vec<CompressionOverride>,
+    /// Merge and split row groups to hold this many rows each
+    #[arg(long, value_name = "ROWS", value_parser = at_least_one())]
+    row_group_rows: Option<usize>,
+    /// Merge and split row groups to hold about this many uncompressed bytes each
+    #[arg(
+        long,
+        value_name = "BYTES",
+        conflicts_with = "row_group_rows",
+        value_parser = at_least_one()
+    )]
+    row_group_bytes: Option<usize>,
+    /// Only write records matching a predicate, e.g. "list_element = 'Name 2'" or
+    /// "len(names) > 3 and not names.list.list_element = 'Name 0'"
+    #[arg(long, value_name = "EXPR")]
+    filter: Option<Predicate>,
+    /// Decode and re-encode every column chunk, even where the output would be written with the
+    /// same compression, encodings and statistics as the input
+    #[arg(long)]
+    no_raw_copy: bool,
+    /// Read and write the records as Arrow record batches instead of through the low-level
+    /// column API
+    #[arg(long, conflicts_with_all = ["schema", "no_raw_copy", "threads"])]
+    arrow: bool,
+    /// Number of threads encoding the columns of a row group
+    #[arg(long, default_value_t = 1, value_parser = at_least_one())]
+    threads: usize,
 }
 
 fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    match cli.command.unwrap_or(Command::Repro) {
+        Command::Repro => repro(),
+        Command::Rewrite(args) => rewrite_file(&args),
+        Command::Concat(args) => concat_files(&args),
+        Command::Split(args) => split_file(&args),
+        Command::Verify { expected, actual } => verify_files(&expected, &actual),
+    }
+}
+
+fn repro() -> Result<()> {
     let props = Arc::new(
         WriterProperties::builder()
             .set_compression(Compression::SNAPPY)
             .build(),
     );
 
-    let schema = Arc::new(
-        parse_message_type(
-            "
-message schema {
-    REQUIRED GROUP names (LIST) {
-        REPEATED GROUP list {
-            REQUIRED BYTE_ARRAY list_element (UTF8);
-        }
-    }
-}
-        ",
-        )
-        .unwrap(),
-    );
+    let schema = Arc::new(parse_message_type(testdata::NAMES_SCHEMA).unwrap());
 
     let bytes = testdata::create_small_parquet_file(Arc::clone(&schema), Arc::clone(&props))?;
 
-    eprintln!("parquet file created: {} bytes", bytes.len());
+    repro_rewrite(bytes, Arc::clone(&props))?;
 
-    let mut reader = SerializedFileReader::new(bytes)?;
-    let reader: &mut dyn FileReader = &mut reader;
+    let schema = Arc::new(parse_message_type(testdata::NULLABLE_NAMES_SCHEMA).unwrap());
 
-    let mut writer = SerializedFileWriter::new(BytesMut::new().writer(), schema, props)?;
+    let bytes = testdata::create_nullable_parquet_file(Arc::clone(&schema), Arc::clone(&props))?;
 
-    let mut values = vec![Default::default(); 5].into_boxed_slice();
-    let mut def_levels = [0i16; 5];
-    let mut rep_levels = [0i16; 5];
+    repro_rewrite(bytes, props)
+}
 
-    {
-        for i in 0..reader.num_row_groups() {
-            let row_group_reader = reader.get_row_group(i)?;
-            let mut column_group_writer = writer.next_row_group()?;
+/// Rewrites `bytes` with small read batches and checks that the records come out unchanged.
+fn repro_rewrite(bytes: Bytes, props: Arc<WriterProperties>) -> Result<()> {
+    eprintln!("parquet file created: {} bytes", bytes.len());
 
-            eprintln!("reader: reading row group {i}");
+    let mut options = RewriteOptions::new(props);
+    options.batch_size = 5;
+    options.level_buffer_capacity = 5;
+    options.raw_copy = false;
+    options.verbose = true;
 
-            for j in 0..row_group_reader.num_columns() {
-                let mut column_reader = row_group_reader.get_column_reader(j)?;
+    let mut output = Vec::new();
+    let report = rewrite(bytes.clone(), &mut output, &options)?;
 
-                let mut column_writer = column_group_writer
-                    .next_column()?
-                    .expect("Expected the writer to have the same number of columns as the reader");
+    eprintln!(
+        "parquet file rewritten: {} bytes, {} row groups, {} rows",
+        output.len(),
+        report.row_groups,
+        report.rows
+    );
 
-                let typed_column_writer = column_writer.typed::<ByteArrayType>();
+    check(verify(bytes, Bytes::from(output))?)
+}
 
-                eprintln!("reader: reading column {j}");
+fn rewrite_file(args: &RewriteArgs) -> Result<()> {
+    let input = args.input.as_path();
+    let output = output_path(&args.output);
 
-                loop {
-                    let (total_records_read, values_read, levels_read) = match &mut column_reader {
-                        parquet::column::reader::ColumnReader::ByteArrayColumnReader(cr) => cr
-                            .read_records(
-                                5,
-                                Some(&mut def_levels),
-                                Some(&mut rep_levels),
-                                &mut values[..],
-                            )?,
-                        _ => panic!("Only implemented for byte arrays"),
-                    };
+    if args.verify && output.is_none() {
+        bail!("--verify needs an output file, the rewritten file cannot be read back from stdout");
+    }
 
-                    eprintln!("reader: {total_records_read} records read");
-                    eprintln!("reader: {values_read} values read");
-                    eprintln!("reader: {levels_read} levels read");
+    let (input_file, metadata) = open_input(input)?;
+    let options = args.write.options(&metadata)?;
 
-                    if values_read == 0 && levels_read == 0 {
-                        eprintln!("reader: no values or levels read, exiting loop");
-                        break;
-                    }
+    let report = write_to(output, &[input], vec![input_file], &options)?;
 
-                    let values_written = typed_column_writer.write_batch(
-                        &values[0..values_read],
-                        Some(&def_levels[0..levels_read]),
-                        Some(&rep_levels[0..levels_read]),
-                    )?;
+    eprintln!(
+        "{}: {} row groups, {} rows, {} rows dropped, {} values rewritten, {} column chunks \
+         copied",
+        input.display(),
+        report.row_groups,
+        report.rows,
+        report.rows_dropped,
+        report.values_written,
+        report.chunks_copied
+    );
 
-                    eprintln!("writer: {values_written} values written");
-                }
+    match output {
+        Some(output) if args.verify => verify_files(input, output),
+        _ => Ok(()),
+    }
+}
 
-                column_writer.close()?;
-            }
+fn concat_files(args: &ConcatArgs) -> Result<()> {
+    let mut input_files = Vec::with_capacity(args.inputs.len());
+    let mut first_metadata = None;
 
-            column_group_writer.close()?;
-        }
+    for input in &args.inputs {
+        let (input_file, metadata) = open_input(input)?;
+        input_files.push(input_file);
+        first_metadata.get_or_insert(metadata);
     }
 
-    let bytes = Bytes::from(writer.into_inner()?.into_inner());
-
-    eprintln!("parquet file rewritten: {} bytes", bytes.len());
+    let metadata = first_metadata.expect("clap requires at least one input");
+    let options = args.write.options(&metadata)?;
+
+    let input_paths: Vec<_> = args.inputs.iter().map(PathBuf::as_path).collect();
+    let report = write_to(
+        output_path(&args.output),
+        &input_paths,
+        input_files,
+        &options,
+    )?;
+
+    eprintln!(
+        "{} files: {} row groups, {} rows, {} rows dropped, {} values rewritten, {} column \
+         chunks copied",
+        args.inputs.len(),
+        report.row_groups,
+        report.rows,
+        report.rows_dropped,
+        report.values_written,
+        report.chunks_copied
+    );
 
     Ok(())
 }
 
-mod testdata {
-    use std::sync::Arc;
-
-    use anyhow::{anyhow, Result};
-    use bytes::{BufMut, Bytes, BytesMut};
-    use parquet::{
-        data_type::{ByteArray, ByteArrayType},
-        file::{properties::WriterProperties, writer::SerializedFileWriter},
+fn split_file(args: &SplitArgs) -> Result<()> {
+    let by = match (&args.by, args.rows) {
+        (Some(column), _) => SplitBy::Column(ColumnPath::new(
+            column.split('.').map(str::to_owned).collect(),
+        )),
+        (None, Some(rows)) => SplitBy::Rows(rows),
+        (None, None) => unreachable!("clap requires --rows or --by"),
     };
 
-    pub fn create_small_parquet_file(
-        schema: Arc<parquet::schema::types::Type>,
-        props: Arc<WriterProperties>,
-    ) -> Result<Bytes> {
-        let mut writer = SerializedFileWriter::new(BytesMut::new().writer(), schema, props)?;
+    let (input_file, metadata) = open_input(&args.input)?;
 
-        {
-            let mut row_group_writer = writer.next_row_group()?;
+    let options = args
+        .common
+        .options(&metadata, &[], metadata.file_metadata().schema_descr())?;
 
-            let mut column_writer = row_group_writer
-                .next_column()?
-                .ok_or(anyhow!("No column"))?;
+    let report = split(input_file, &by, &args.directory, &options)?;
 
-            let typed = column_writer.typed::<ByteArrayType>();
-
-            let mut repeated_writer = RepeatedWriter::new();
+    for file in &report.files {
+        println!("{}", file.display());
+    }
 
-            repeated_writer.push(names(4).into_iter());
-            repeated_writer.push(names(4).into_iter());
+    eprintln!(
+        "{}: {} files, {} row groups, {} rows, {} values written",
+        args.input.display(),
+        report.files.len(),
+        report.row_groups,
+        report.rows,
+        report.values_written
+    );
 
-            let _ = typed.write_batch(
-                repeated_writer.values(),
-                repeated_writer.def_levels(),
-                repeated_writer.rep_levels(),
-            )?;
+    Ok(())
+}
 
-            column_writer.close()?;
+impl CommonArgs {
+    /// Builds the rewrite options with the shared flags applied, taking preserved properties from
+    /// `metadata` and setting the compression of the columns in `overrides`, which must be leaf
+    /// columns of the output schema `schema`.
+    fn options(
+        &self,
+        metadata: &ParquetMetaData,
+        overrides: &[CompressionOverride],
+        schema: &SchemaDescriptor,
+    ) -> Result<RewriteOptions> {
+        let props = if self.preserve {
+            preserved_properties(metadata)
+        } else {
+            WriterProperties::builder().set_compression(Compression::SNAPPY)
+        };
+
+        let props = with_compression_overrides(props, overrides, schema)?;
+
+        let mut options = RewriteOptions::new(Arc::new(props.build()));
+        options.batch_size = self.batch_size;
+        options.level_buffer_capacity = self.level_buffer_capacity;
+        options.verbose = self.verbose;
+
+        Ok(options)
+    }
+}
 
-            row_group_writer.close()?;
+impl WriteArgs {
+    /// Builds the rewrite options, taking preserved properties from `metadata`.
+    fn options(&self, metadata: &ParquetMetaData) -> Result<RewriteOptions> {
+        let schema = self.schema.as_deref().map(read_schema).transpose()?;
+        let output_schema = SchemaDescriptor::new(output_schema(
+            metadata.file_metadata().schema_descr(),
+            schema.as_ref(),
+            &self.columns,
+        )?);
+
+        let mut options =
+            self.common
+                .options(metadata, &self.column_compression, &output_schema)?;
+        options.schema = schema;
+        options.projection = self.columns.clone();
+        options.filter = self.filter.clone();
+        options.row_group_size = match (self.row_group_rows, self.row_group_bytes) {
+            (Some(rows), _) => Some(RowGroupSize::Rows(rows)),
+            (None, Some(bytes)) => Some(RowGroupSize::Bytes(bytes)),
+            (None, None) => None,
+        };
+        options.raw_copy = !self.no_raw_copy;
+        options.threads = self.threads;
+
+        if self.arrow {
+            options.mode = RewriteMode::Arrow;
         }
 
-        Ok(Bytes::from(writer.into_inner()?.into_inner()))
+        Ok(options)
     }
+}
 
-    #[derive(Debug, Default)]
-    pub struct RepeatedWriter {
-        values: Vec<ByteArray>,
-        def_levels: Vec<i16>,
-        rep_levels: Vec<i16>,
-    }
+fn open_input(path: &Path) -> Result<(FileChunkReader, ParquetMetaData)> {
+    let file = FileChunkReader::open(path)
+        .with_context(|| format!("Failed to open {}", path.display()))?;
 
-    impl RepeatedWriter {
-        fn new() -> Self {
-            RepeatedWriter {
-                values: Default::default(),
-                def_levels: Default::default(),
-                rep_levels: Default::default(),
-            }
-        }
+    let metadata = parse_metadata(&file)
+        .with_context(|| format!("Failed to read Parquet metadata from {}", path.display()))?;
 
-        fn push<Iter: ExactSizeIterator<Item = T>, T>(&mut self, values: Iter)
-        where
-            T: Into<ByteArray>,
-        {
-            let num = values.len();
-            if num == 0 {
-                self.def_levels.push(0);
-                self.rep_levels.push(0);
-            } else {
-                self.def_levels.resize(self.def_levels.len() + num, 1);
-                self.rep_levels.push(0);
-                self.rep_levels.resize(self.rep_levels.len() + num - 1, 1);
-                self.values.extend(values.map(|val| val.into()));
-            }
-        }
+    Ok((file, metadata))
+}
 
-        fn values(&self) -> &[ByteArray] {
-            &self.values
-        }
+/// Parses a count that must not be 0.
+fn at_least_one() -> RangedU64ValueParser<usize> {
+    RangedU64ValueParser::new().range(1..)
+}
+
+/// The output file, or `None` for stdout.
+fn output_path(output: &Option<PathBuf>) -> Option<&Path> {
+    output.as_deref().filter(|path| *path != Path::new("-"))
+}
+
+fn verify_files(expected: &Path, actual: &Path) -> Result<()> {
+    let open = |path: &Path| {
+        FileChunkReader::open(path).with_context(|| format!("Failed to open {}", path.display()))
+    };
 
-        fn def_levels(&self) -> Option<&[i16]> {
-            Some(&self.def_levels)
+    check(verify(open(expected)?, open(actual)?)?)
+}
+
+fn check(report: VerifyReport) -> Result<()> {
+    match report.divergence {
+        Some(divergence) => bail!("verify: {divergence}"),
+        None => {
+            eprintln!(
+                "verify: {} columns, {} records, {} levels identical",
+                report.columns, report.records, report.levels
+            );
+            Ok(())
         }
+    }
+}
+
+fn read_schema(path: &Path) -> Result<TypePtr> {
+    let message_type = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    let schema = parse_message_type(&message_type)
+        .with_context(|| format!("Failed to parse the schema in {}", path.display()))?;
 
-        fn rep_levels(&self) -> Option<&[i16]> {
-            Some(&self.rep_levels)
+    Ok(Arc::new(schema))
+}
+
+/// Writes `inputs`, read from `input_paths`, to `output` or to stdout.
+fn write_to(
+    output: Option<&Path>,
+    input_paths: &[&Path],
+    inputs: Vec<FileChunkReader>,
+    options: &RewriteOptions,
+) -> Result<RewriteReport> {
+    match output {
+        Some(path) => {
+            check_not_an_input(path, input_paths)?;
+
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            write_sink(file, inputs, options)
         }
+        None => write_sink(std::io::stdout(), inputs, options),
     }
+}
 
-    fn names(count: usize) -> Vec<Vec<u8>> {
-        (0..count)
-            .map(|i| format!("Name {i}").into_bytes())
-            .collect()
+/// Fails if `output` is one of `inputs`, which creating it would truncate before it is read.
+fn check_not_an_input(output: &Path, inputs: &[&Path]) -> Result<()> {
+    // An output that does not exist yet cannot be an input.
+    let Ok(output) = output.canonicalize() else {
+        return Ok(());
+    };
+
+    for input in inputs {
+        let canonical = input
+            .canonicalize()
+            .with_context(|| format!("Failed to resolve {}", input.display()))?;
+
+        if canonical == output {
+            bail!(
+                "Output {} is also an input, write to another file instead",
+                input.display()
+            );
+        }
     }
+
+    Ok(())
+}
+
+fn write_sink<W: Write + Send>(
+    sink: W,
+    inputs: Vec<FileChunkReader>,
+    options: &RewriteOptions,
+) -> Result<RewriteReport> {
+    let mut sink = BufWriter::new(sink);
+    let report = concat(inputs, &mut sink, options)?;
+    sink.flush()?;
+
+    Ok(report)
 }
@@ -0,0 +1,161 @@
+//! Deriving writer properties from the metadata of an existing file.
+
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, Error, Result};
+use parquet::{
+    basic::{Compression, Encoding},
+    file::{
+        metadata::{ColumnChunkMetaData, ParquetMetaData},
+        properties::{EnabledStatistics, WriterProperties, WriterPropertiesBuilder, WriterVersion},
+    },
+    schema::types::{ColumnPath, SchemaDescriptor},
+};
+
+/// A compression codec for a single column, parsed from `path=CODEC` where the path is the dotted
+/// path of a leaf column and the codec is written as for `Compression`, e.g.
+/// `names.list.list_element=ZSTD(3)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompressionOverride {
+    pub column: ColumnPath,
+    pub compression: Compression,
+}
+
+impl FromStr for CompressionOverride {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (column, compression) = s
+            .split_once('=')
+            .ok_or_else(|| anyhow!("Expected COLUMN=CODEC, got {s:?}"))?;
+
+        let compression = compression
+            .parse()
+            .map_err(|err| anyhow!("Invalid codec for column {column}: {err}"))?;
+
+        Ok(CompressionOverride {
+            column: ColumnPath::new(column.split('.').map(str::to_owned).collect()),
+            compression,
+        })
+    }
+}
+
+/// Sets the compression of each column in `overrides` on top of `builder`. Fails if a column is
+/// not a leaf column of `schema`.
+pub fn with_compression_overrides(
+    mut builder: WriterPropertiesBuilder,
+    overrides: &[CompressionOverride],
+    schema: &SchemaDescriptor,
+) -> Result<WriterPropertiesBuilder> {
+    for CompressionOverride {
+        column,
+        compression,
+    } in overrides
+    {
+        if !schema.columns().iter().any(|descr| descr.path() == column) {
+            bail!("Cannot set the compression of {column}, the schema has no such leaf column");
+        }
+
+        builder = builder.set_column_compression(column.clone(), *compression);
+    }
+
+    Ok(builder)
+}
+
+/// Returns a builder for writer properties that reproduce how the file described by `metadata`
+/// was written: format version, created_by, key value metadata and, per column, compression,
+/// dictionary encoding, fallback encoding and statistics level.
+///
+/// Per-column settings are taken from the first row group. Compression levels are not stored in
+/// Parquet files, so codecs that have levels use their default level.
+pub fn preserved_properties(metadata: &ParquetMetaData) -> WriterPropertiesBuilder {
+    let file_metadata = metadata.file_metadata();
+
+    let mut builder = WriterProperties::builder()
+        .set_writer_version(writer_version(file_metadata.version()))
+        .set_key_value_metadata(file_metadata.key_value_metadata().cloned());
+
+    if let Some(created_by) = file_metadata.created_by() {
+        builder = builder.set_created_by(created_by.to_owned());
+    }
+
+    let Some(row_group) = metadata.row_groups().first() else {
+        return builder;
+    };
+
+    for column in row_group.columns() {
+        let path = column.column_path().clone();
+
+        builder = builder
+            .set_column_compression(path.clone(), column.compression())
+            .set_column_dictionary_enabled(path.clone(), has_dictionary(column))
+            .set_column_statistics_enabled(path.clone(), statistics_level(column));
+
+        if let Some(encoding) = data_encoding(column) {
+            builder = builder.set_column_encoding(path, encoding);
+        }
+    }
+
+    builder
+}
+
+/// Whether writing the values of `column`, from a file of format `version`, with `properties`
+/// would produce a column chunk like `column`: same data page version, compression, dictionary
+/// encoding, fallback encoding and statistics level, and no bloom filter to build. Such a chunk can
+/// be copied without decoding it.
+pub fn chunk_matches_properties(
+    column: &ColumnChunkMetaData,
+    version: i32,
+    properties: &WriterProperties,
+) -> bool {
+    let path = column.column_path();
+
+    writer_version(version) == properties.writer_version()
+        && column.compression() == properties.compression(path)
+        && has_dictionary(column) == properties.dictionary_enabled(path)
+        && data_encoding(column) == properties.encoding(path)
+        && statistics_level(column) == properties.statistics_enabled(path)
+        && properties.bloom_filter_properties(path).is_none()
+}
+
+/// The writer version that writes files of format `version`.
+fn writer_version(version: i32) -> WriterVersion {
+    match version {
+        1 => WriterVersion::PARQUET_1_0,
+        _ => WriterVersion::PARQUET_2_0,
+    }
+}
+
+fn has_dictionary(column: &ColumnChunkMetaData) -> bool {
+    column.dictionary_page_offset().is_some()
+        || column.encodings().iter().any(|encoding| {
+            matches!(
+                encoding,
+                Encoding::PLAIN_DICTIONARY | Encoding::RLE_DICTIONARY
+            )
+        })
+}
+
+/// The encoding used for data pages that are not dictionary encoded, if it is one the writer can
+/// be asked for. Level encodings and dictionary encodings are skipped.
+fn data_encoding(column: &ColumnChunkMetaData) -> Option<Encoding> {
+    column.encodings().iter().copied().find(|encoding| {
+        matches!(
+            encoding,
+            Encoding::DELTA_BINARY_PACKED
+                | Encoding::DELTA_LENGTH_BYTE_ARRAY
+                | Encoding::DELTA_BYTE_ARRAY
+                | Encoding::BYTE_STREAM_SPLIT
+        )
+    })
+}
+
+fn statistics_level(column: &ColumnChunkMetaData) -> EnabledStatistics {
+    if column.column_index_offset().is_some() {
+        EnabledStatistics::Page
+    } else if column.statistics().is_some() {
+        EnabledStatistics::Chunk
+    } else {
+        EnabledStatistics::None
+    }
+}
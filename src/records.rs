@@ -0,0 +1,450 @@
+//! Reading whole records of a column chunk with `read_records`.
+
+use std::ops::Range;
+
+use anyhow::Result;
+use parquet::{
+    column::{reader::ColumnReaderImpl, writer::ColumnWriterImpl},
+    data_type::DataType,
+    schema::types::ColumnDescriptor,
+};
+
+use crate::RewriteOptions;
+
+/// Values and levels of a run of records of one leaf column.
+pub struct ColumnRecords<T: DataType> {
+    pub values: Vec<T::T>,
+    pub def_levels: Vec<i16>,
+    pub rep_levels: Vec<i16>,
+    max_def_level: i16,
+    max_rep_level: i16,
+}
+
+/// The levels and values of a single record within a [`ColumnRecords`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordRange {
+    pub levels: Range<usize>,
+    pub values: Range<usize>,
+}
+
+impl<T: DataType> ColumnRecords<T> {
+    pub fn new(descr: &ColumnDescriptor) -> Self {
+        ColumnRecords {
+            values: Vec::new(),
+            def_levels: Vec::new(),
+            rep_levels: Vec::new(),
+            max_def_level: descr.max_def_level(),
+            max_rep_level: descr.max_rep_level(),
+        }
+    }
+
+    pub fn max_rep_level(&self) -> i16 {
+        self.max_rep_level
+    }
+
+    pub fn num_levels(&self) -> usize {
+        self.def_levels.len()
+    }
+
+    pub fn num_records(&self) -> usize {
+        if self.max_rep_level > 0 {
+            self.rep_levels
+                .iter()
+                .filter(|rep_level| **rep_level == 0)
+                .count()
+        } else {
+            self.num_levels()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.def_levels.is_empty()
+    }
+
+    /// The definition level at `index`. Columns without definition levels are always defined.
+    pub fn def_level(&self, index: usize) -> i16 {
+        if self.max_def_level > 0 {
+            self.def_levels[index]
+        } else {
+            0
+        }
+    }
+
+    /// The repetition level at `index`. Columns without repetition levels always start a record.
+    pub fn rep_level(&self, index: usize) -> i16 {
+        if self.max_rep_level > 0 {
+            self.rep_levels[index]
+        } else {
+            0
+        }
+    }
+
+    /// Number of values belonging to the first `num_levels` levels.
+    fn num_values(&self, num_levels: usize) -> usize {
+        if self.max_def_level > 0 {
+            self.def_levels[..num_levels]
+                .iter()
+                .filter(|def_level| **def_level == self.max_def_level)
+                .count()
+        } else {
+            num_levels
+        }
+    }
+
+    /// The level and value ranges of every record, in order.
+    pub fn records(&self) -> Vec<RecordRange> {
+        let mut records: Vec<RecordRange> = Vec::new();
+        let mut value = 0;
+
+        for level in 0..self.num_levels() {
+            if self.rep_level(level) == 0 || records.is_empty() {
+                records.push(RecordRange {
+                    levels: level..level,
+                    values: value..value,
+                });
+            }
+
+            let record = records.last_mut().expect("a record was just started");
+            record.levels.end = level + 1;
+
+            if self.def_level(level) == self.max_def_level {
+                value += 1;
+                record.values.end = value;
+            }
+        }
+
+        records
+    }
+
+    /// Removes the first `num_levels` levels and their values and returns them. `num_levels`
+    /// must be on a record boundary.
+    pub fn split_to(&mut self, num_levels: usize) -> ColumnRecords<T> {
+        let num_values = self.num_values(num_levels);
+
+        ColumnRecords {
+            values: self.values.drain(..num_values).collect(),
+            def_levels: self.def_levels.drain(..num_levels).collect(),
+            rep_levels: self.rep_levels.drain(..num_levels).collect(),
+            max_def_level: self.max_def_level,
+            max_rep_level: self.max_rep_level,
+        }
+    }
+
+    /// Removes the first `num_records` records, or all of them if there are fewer, and returns
+    /// them.
+    pub fn split_records(&mut self, num_records: usize) -> ColumnRecords<T> {
+        let num_levels = self
+            .records()
+            .get(num_records)
+            .map_or(self.num_levels(), |record| record.levels.start);
+
+        self.split_to(num_levels)
+    }
+
+    /// Keeps only the records for which `keep` returns true, given the index of the record.
+    pub fn retain_records(&mut self, mut keep: impl FnMut(usize) -> bool) {
+        let mut retained = ColumnRecords {
+            values: Vec::new(),
+            def_levels: Vec::new(),
+            rep_levels: Vec::new(),
+            max_def_level: self.max_def_level,
+            max_rep_level: self.max_rep_level,
+        };
+
+        for (i, record) in self.records().iter().enumerate() {
+            if keep(i) {
+                retained.push_record(self, record);
+            }
+        }
+
+        *self = retained;
+    }
+
+    /// Appends the record of `other` at `record` to the end.
+    pub fn push_record(&mut self, other: &ColumnRecords<T>, record: &RecordRange) {
+        self.values
+            .extend_from_slice(&other.values[record.values.clone()]);
+        self.def_levels
+            .extend_from_slice(&other.def_levels[record.levels.clone()]);
+        self.rep_levels
+            .extend_from_slice(&other.rep_levels[record.levels.clone()]);
+    }
+
+    /// Removes all records.
+    pub fn clear(&mut self) {
+        self.values.clear();
+        self.def_levels.clear();
+        self.rep_levels.clear();
+    }
+
+    /// Writes all records to `column_writer`, returning the number of values written.
+    pub fn write(&self, column_writer: &mut ColumnWriterImpl<T>) -> Result<usize> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+
+        Ok(column_writer.write_batch(
+            &self.values,
+            Some(&self.def_levels),
+            Some(&self.rep_levels),
+        )?)
+    }
+}
+
+/// Reads a column chunk with `read_records` and hands out only complete records, holding back
+/// a record until its last level has been read.
+pub struct RecordReader<T: DataType> {
+    column_reader: ColumnReaderImpl<T>,
+    buffers: ReadBuffers<T>,
+    pending: ColumnRecords<T>,
+    num_rows: usize,
+    batch_size: usize,
+    verbose: bool,
+    finished: bool,
+    /// Whether the last call filled the buffers without completing a record.
+    long_record: bool,
+    /// Number of levels at the start of `pending` already searched for the start of a record.
+    levels_scanned: usize,
+    records_read: usize,
+    values_read: usize,
+    levels_read: usize,
+}
+
+impl<T: DataType> RecordReader<T> {
+    /// `num_rows` is the number of rows of the row group according to its metadata.
+    pub fn new(
+        column_reader: ColumnReaderImpl<T>,
+        descr: &ColumnDescriptor,
+        num_rows: usize,
+        options: &RewriteOptions,
+    ) -> Self {
+        RecordReader {
+            column_reader,
+            buffers: ReadBuffers::new(options.level_buffer_capacity),
+            pending: ColumnRecords::new(descr),
+            num_rows,
+            batch_size: options.batch_size,
+            verbose: options.verbose,
+            finished: false,
+            long_record: false,
+            levels_scanned: 0,
+            records_read: 0,
+            values_read: 0,
+            levels_read: 0,
+        }
+    }
+
+    pub fn values_read(&self) -> usize {
+        self.values_read
+    }
+
+    pub fn levels_read(&self) -> usize {
+        self.levels_read
+    }
+
+    /// Returns the next complete records, or `None` once the column chunk is exhausted.
+    pub fn next_records(&mut self) -> Result<Option<ColumnRecords<T>>> {
+        while !self.finished {
+            if self.records_read >= self.num_rows {
+                self.finished = true;
+                break;
+            }
+
+            // `read_records` reads no more levels than records requested. While a record does not
+            // fit, request as many records as the buffers hold levels so that growing them helps.
+            let max_records = if self.long_record {
+                self.batch_size.max(self.buffers.capacity())
+            } else {
+                self.batch_size
+            };
+
+            let (total_records_read, values_read, levels_read) = self.column_reader.read_records(
+                max_records,
+                Some(&mut self.buffers.def_levels[..]),
+                Some(&mut self.buffers.rep_levels[..]),
+                &mut self.buffers.values[..],
+            )?;
+
+            if self.verbose {
+                eprintln!("reader: {total_records_read} records read");
+                eprintln!("reader: {values_read} values read");
+                eprintln!("reader: {levels_read} levels read");
+            }
+
+            // The reader may only count the last record once it knows the column chunk has
+            // ended, so running out of levels is the end of the chunk even if fewer records were
+            // counted.
+            if total_records_read == 0 && levels_read == 0 {
+                if self.verbose {
+                    eprintln!(
+                        "reader: column chunk ended after {} records",
+                        self.records_read
+                    );
+                }
+                self.finished = true;
+                break;
+            }
+
+            self.pending
+                .values
+                .extend_from_slice(&self.buffers.values[..values_read]);
+            self.pending
+                .def_levels
+                .extend_from_slice(&self.buffers.def_levels[..levels_read]);
+            self.pending
+                .rep_levels
+                .extend_from_slice(&self.buffers.rep_levels[..levels_read]);
+
+            self.records_read += total_records_read;
+            self.values_read += values_read;
+            self.levels_read += levels_read;
+
+            // Reading as many levels as allowed without a single complete record means one record
+            // has more levels than that, make room for it before the next call.
+            self.long_record =
+                total_records_read == 0 && levels_read == max_records.min(self.buffers.capacity());
+
+            if self.long_record && levels_read == self.buffers.capacity() {
+                self.buffers.grow();
+
+                if self.verbose {
+                    eprintln!("reader: level buffers grown to {}", self.buffers.capacity());
+                }
+            }
+
+            // The last record may continue in the next call, so only the records before it are
+            // complete. Without repetition every level is a record of its own. Levels held back
+            // have been searched before, so only the new ones are.
+            let complete_levels = if self.pending.max_rep_level() > 0 {
+                self.pending.rep_levels[self.levels_scanned..]
+                    .iter()
+                    .rposition(|rep_level| *rep_level == 0)
+                    .map_or(0, |position| self.levels_scanned + position)
+            } else {
+                self.pending.num_levels()
+            };
+
+            self.levels_scanned = self.pending.num_levels() - complete_levels;
+
+            if complete_levels < self.pending.num_levels() && self.verbose {
+                eprintln!(
+                    "reader: holding back {} levels of a possibly partial record",
+                    self.pending.num_levels() - complete_levels
+                );
+            }
+
+            if complete_levels > 0 {
+                return Ok(Some(self.pending.split_to(complete_levels)));
+            }
+        }
+
+        // Whatever is still held back is complete now that the chunk has ended.
+        if self.pending.is_empty() {
+            Ok(None)
+        } else {
+            let num_levels = self.pending.num_levels();
+            Ok(Some(self.pending.split_to(num_levels)))
+        }
+    }
+}
+
+/// The value and level buffers filled by `read_records`, which never reads more levels than
+/// they can hold.
+struct ReadBuffers<T: DataType> {
+    values: Vec<T::T>,
+    def_levels: Vec<i16>,
+    rep_levels: Vec<i16>,
+}
+
+impl<T: DataType> ReadBuffers<T> {
+    fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+
+        ReadBuffers {
+            values: vec![T::T::default(); capacity],
+            def_levels: vec![0; capacity],
+            rep_levels: vec![0; capacity],
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        self.def_levels.len()
+    }
+
+    fn grow(&mut self) {
+        let capacity = self.capacity() * 2;
+
+        self.values.resize(capacity, T::T::default());
+        self.def_levels.resize(capacity, 0);
+        self.rep_levels.resize(capacity, 0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use parquet::{
+        column::reader::get_typed_column_reader,
+        data_type::ByteArrayType,
+        file::{
+            properties::WriterProperties, reader::FileReader,
+            serialized_reader::SerializedFileReader,
+        },
+    };
+
+    use super::*;
+    use crate::{
+        parse_schema,
+        testdata::{self, RepeatedWriter},
+    };
+
+    #[test]
+    fn level_buffers_grow_to_hold_a_record_longer_than_them() -> Result<()> {
+        let schema = Arc::new(parse_schema(testdata::NAMES_SCHEMA));
+        let props = Arc::new(WriterProperties::builder().build());
+
+        let mut repeated_writer = RepeatedWriter::new();
+        repeated_writer.push(testdata::names(1).into_iter());
+        repeated_writer.push(testdata::names(100).into_iter());
+        repeated_writer.push(testdata::names(2).into_iter());
+
+        let bytes = testdata::create_parquet_file(schema, props.clone(), &repeated_writer)?;
+        let file_reader = SerializedFileReader::new(bytes)?;
+        let row_group_reader = file_reader.get_row_group(0)?;
+        let descr = file_reader
+            .metadata()
+            .file_metadata()
+            .schema_descr()
+            .column(0);
+
+        let mut options = RewriteOptions::new(props);
+        options.batch_size = 5;
+        options.level_buffer_capacity = 5;
+
+        let mut reader = RecordReader::<ByteArrayType>::new(
+            get_typed_column_reader(row_group_reader.get_column_reader(0)?),
+            &descr,
+            3,
+            &options,
+        );
+
+        let mut records = Vec::new();
+        while let Some(batch) = reader.next_records()? {
+            records.extend(batch.records().iter().map(|record| record.levels.len()));
+        }
+
+        assert_eq!(records, [1, 100, 2]);
+        assert_eq!(reader.levels_read(), 103);
+
+        // The buffers only grow when a call fills them, so a single call read at least half of
+        // their final capacity, many times the initial 5 levels.
+        assert!(
+            reader.buffers.capacity() >= 64,
+            "buffers only grew to {} levels",
+            reader.buffers.capacity()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,692 @@
+//! Rewriting whole Parquet files row group by row group.
+
+use std::{
+    io::Write,
+    ops::RangeInclusive,
+    sync::{Arc, Mutex},
+    thread,
+};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use parquet::{
+    column::writer::{get_column_writer, ColumnCloseResult},
+    file::{
+        metadata::ColumnChunkMetaData,
+        properties::WriterPropertiesPtr,
+        reader::{ChunkReader, FileReader, Length},
+        serialized_reader::SerializedFileReader,
+        writer::{SerializedFileWriter, SerializedPageWriter, TrackedWrite},
+    },
+    format::{ColumnIndex, OffsetIndex},
+    schema::types::{ColumnDescPtr, ColumnDescriptor, SchemaDescriptor, TypePtr},
+    thrift::TSerializable,
+};
+use thrift::protocol::TCompactInputProtocol;
+
+use crate::{
+    arrow::concat_arrow,
+    copy::{
+        close_column_writer, column_copier, write_nulls, ColumnCopier, ColumnCopyReport,
+        InputRowGroup, RowGroupMasks,
+    },
+    filter::Predicate,
+    properties::chunk_matches_properties,
+    schema::{map_columns, output_schema, ColumnSelector, ColumnSource},
+};
+
+/// Target size of the row groups of a rewritten file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowGroupSize {
+    /// Number of rows per row group.
+    Rows(usize),
+    /// Uncompressed bytes per row group, converted to a number of rows using the average row size
+    /// of the input.
+    Bytes(usize),
+}
+
+/// Default number of records per `read_records` call and initial level buffer capacity.
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+
+/// How the records of the input are read and written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RewriteMode {
+    /// Column by column with `read_records` and `write_batch`.
+    #[default]
+    LowLevel,
+    /// Through Arrow record batches with `ParquetRecordBatchReader` and `ArrowWriter`, see
+    /// [`concat_arrow`].
+    Arrow,
+}
+
+/// Options controlling how a file is rewritten.
+#[derive(Debug, Clone)]
+pub struct RewriteOptions {
+    /// Schema of the rewritten file. Defaults to the schema of the input. Leaf columns of an
+    /// explicit schema are matched to those of the input by path, see [`map_columns`].
+    pub schema: Option<TypePtr>,
+    /// Properties used for the writer of the rewritten file.
+    pub properties: WriterPropertiesPtr,
+    /// Leaf columns to keep, all of them if empty. Applied to the explicit schema if there is
+    /// one, see [`output_schema`].
+    pub projection: Vec<ColumnSelector>,
+    /// Only records matching this predicate are written. Row groups left without records are
+    /// dropped. The predicate is evaluated twice for every row group with records left, once to
+    /// size the output row groups and once more while it is copied, so that only the masks of
+    /// the row groups being copied are held in memory.
+    pub filter: Option<Predicate>,
+    /// Size of the row groups of the rewritten file. By default every row group of the input is
+    /// written as a row group of its own.
+    pub row_group_size: Option<RowGroupSize>,
+    /// Number of records requested per `read_records` call, which reads no more levels than
+    /// that either. While a single record does not fit the read buffers, as many records are
+    /// requested as the buffers hold levels.
+    pub batch_size: usize,
+    /// Initial number of levels the read buffers hold. The buffers grow when a single record
+    /// does not fit.
+    pub level_buffer_capacity: usize,
+    /// Copy the column chunks of input row groups that are written whole as they are, without
+    /// decoding them, when the writer properties would produce the same chunk, see
+    /// [`chunk_matches_properties`]. The page indexes of copied chunks are copied along.
+    pub raw_copy: bool,
+    /// Whether to copy records through the low-level column API or through Arrow.
+    pub mode: RewriteMode,
+    /// Number of threads encoding the columns of a row group. With more than one, column chunks
+    /// are encoded in memory and appended to the row group in order once all are done. The file
+    /// reads back the same as with one thread, but the column metadata written inline with the
+    /// appended chunks keeps the offsets they had in memory, so the bytes differ.
+    pub threads: usize,
+    /// Print every `read_records` and `write_batch` call to stderr.
+    pub verbose: bool,
+}
+
+impl RewriteOptions {
+    pub fn new(properties: WriterPropertiesPtr) -> Self {
+        RewriteOptions {
+            schema: None,
+            properties,
+            projection: Vec::new(),
+            filter: None,
+            row_group_size: None,
+            batch_size: DEFAULT_BATCH_SIZE,
+            level_buffer_capacity: DEFAULT_BATCH_SIZE,
+            raw_copy: true,
+            mode: RewriteMode::LowLevel,
+            threads: 1,
+            verbose: false,
+        }
+    }
+}
+
+/// Summary of a finished rewrite.
+#[derive(Debug, Default, Clone)]
+pub struct RewriteReport {
+    pub row_groups: usize,
+    pub columns: usize,
+    pub rows: i64,
+    pub rows_dropped: usize,
+    /// Values and levels are only counted in [`RewriteMode::LowLevel`].
+    pub values_read: usize,
+    pub levels_read: usize,
+    pub values_written: usize,
+    /// Column chunks copied without decoding them.
+    pub chunks_copied: usize,
+}
+
+impl RewriteReport {
+    fn add_column(&mut self, column: &ColumnCopyReport) {
+        self.values_read += column.values_read;
+        self.levels_read += column.levels_read;
+        self.values_written += column.values_written;
+    }
+}
+
+/// Reads every row group of the Parquet file in `reader` and writes it again to `sink`, copying
+/// each column record by record with `read_records` and `write_batch`, or through Arrow in
+/// [`RewriteMode::Arrow`].
+pub fn rewrite<R, W>(reader: R, sink: W, options: &RewriteOptions) -> Result<RewriteReport>
+where
+    R: ChunkReader + 'static,
+    W: Write + Send,
+{
+    concat(vec![reader], sink, options)
+}
+
+/// Rejects options that leave nothing to read or write per call or per row group, or no thread
+/// to do it on.
+pub(crate) fn check_options(options: &RewriteOptions) -> Result<()> {
+    if options.batch_size == 0 {
+        bail!("The batch size must be at least 1");
+    }
+
+    if matches!(
+        options.row_group_size,
+        Some(RowGroupSize::Rows(0) | RowGroupSize::Bytes(0))
+    ) {
+        bail!("The row group size must be at least 1");
+    }
+
+    if options.threads == 0 {
+        bail!("At least 1 thread is needed to encode the columns");
+    }
+
+    Ok(())
+}
+
+/// Writes the row groups of all Parquet files in `readers`, in order, to a single file in `sink`.
+/// All inputs must have the same schema.
+pub fn concat<R, W>(readers: Vec<R>, sink: W, options: &RewriteOptions) -> Result<RewriteReport>
+where
+    R: ChunkReader + 'static,
+    W: Write + Send,
+{
+    check_options(options)?;
+
+    if options.mode == RewriteMode::Arrow {
+        return concat_arrow(readers, sink, options);
+    }
+
+    let inputs = readers
+        .into_iter()
+        .map(|reader| {
+            let chunks = Arc::new(reader);
+            let reader = SerializedFileReader::new(SharedChunkReader(Arc::clone(&chunks)))?;
+            Ok(Input { chunks, reader })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let Some(first) = inputs.first() else {
+        bail!("Expected at least one input file");
+    };
+
+    let input_schema = first.reader.metadata().file_metadata().schema_descr();
+
+    for (i, input) in inputs.iter().enumerate().skip(1) {
+        if input.reader.metadata().file_metadata().schema() != input_schema.root_schema() {
+            bail!("Input {i} has a different schema than the first input");
+        }
+    }
+
+    let schema = output_schema(input_schema, options.schema.as_ref(), &options.projection)?;
+
+    let output_schema = SchemaDescriptor::new(schema.clone());
+    let sources = map_columns(input_schema, &output_schema)?;
+
+    let mut writer = SerializedFileWriter::new(sink, schema, options.properties.clone())?;
+
+    let mut report = RewriteReport::default();
+
+    let mut row_groups = Vec::new();
+    let mut origins = Vec::new();
+    let mut row_group_sizes = Vec::new();
+
+    for (input_index, input) in inputs.iter().enumerate() {
+        for i in 0..input.reader.num_row_groups() {
+            let row_group_reader = input.reader.get_row_group(i)?;
+            let num_rows = usize::try_from(row_group_reader.metadata().num_rows())?;
+
+            let mask = match &options.filter {
+                Some(filter) => {
+                    Some(filter.evaluate(row_group_reader.as_ref(), num_rows, options)?)
+                }
+                None => None,
+            };
+
+            let num_kept = mask
+                .as_ref()
+                .map_or(num_rows, |mask| mask.iter().filter(|keep| **keep).count());
+
+            if options.verbose && num_kept == 0 {
+                eprintln!(
+                    "reader: no records of row group {i} of input {input_index} match the filter"
+                );
+            }
+
+            report.rows_dropped += num_rows - num_kept;
+            row_group_sizes.push(num_kept);
+            origins.push(input_index);
+            row_groups.push(InputRowGroup {
+                reader: &input.reader,
+                index: i,
+                num_kept,
+                filtered: num_kept < num_rows,
+            });
+        }
+    }
+
+    // Whole row groups are copied as they are where possible, split and merged ones record by
+    // record.
+    let plan: Vec<_> = match options.row_group_size {
+        None => row_group_sizes
+            .iter()
+            .enumerate()
+            .filter(|(_, size)| **size > 0)
+            .map(|(i, size)| {
+                if options.raw_copy && !row_groups[i].filtered {
+                    OutputRowGroup::Whole(i)
+                } else {
+                    OutputRowGroup::Records(*size)
+                }
+            })
+            .collect(),
+        Some(size) => {
+            let rows_per_row_group = match size {
+                RowGroupSize::Rows(rows) => rows,
+                // A target smaller than the average row still holds one row.
+                RowGroupSize::Bytes(bytes) => rows_for_bytes(&row_groups, &sources, bytes).max(1),
+            };
+
+            split_rows(row_group_sizes.iter().sum(), rows_per_row_group)
+                .into_iter()
+                .map(OutputRowGroup::Records)
+                .collect()
+        }
+    };
+
+    let mut copiers: Vec<_> = sources
+        .iter()
+        .map(|source| match *source {
+            ColumnSource::Input(j) => Some(column_copier(
+                &row_groups,
+                j,
+                input_schema.column(j),
+                options,
+            )),
+            ColumnSource::Null => None,
+        })
+        .collect();
+
+    // Number of records to copy up to and including each input row group.
+    let row_group_ends: Vec<usize> = row_group_sizes
+        .iter()
+        .scan(0, |end, size| {
+            *end += size;
+            Some(*end)
+        })
+        .collect();
+
+    let mut masks = RowGroupMasks::new();
+    let mut records_copied: usize = 0;
+
+    for output_row_group in plan {
+        let (num_rows, whole) = match output_row_group {
+            OutputRowGroup::Records(num_rows) => (num_rows, None),
+            OutputRowGroup::Whole(i) => (row_group_sizes[i], Some(i)),
+        };
+
+        if let Some(filter) = &options.filter {
+            // The copiers may still have to skip the dropped records at the end of the row group
+            // the previous output row group ended in.
+            let row_group_of = |record: usize| row_group_ends.partition_point(|end| *end <= record);
+            let first = row_group_of(records_copied.saturating_sub(1));
+            let last = row_group_of(records_copied + num_rows - 1);
+
+            update_masks(&mut masks, &row_groups, first..=last, filter, options)?;
+        }
+
+        records_copied += num_rows;
+
+        if options.verbose {
+            eprintln!(
+                "writer: writing row group {} with {num_rows} rows",
+                report.row_groups
+            );
+        }
+
+        let whole_input = whole.map(|i| (&inputs[origins[i]], row_groups[i].index));
+
+        // Column chunks of a whole input row group that can be copied as they are.
+        let raw_chunks: Vec<_> = sources
+            .iter()
+            .enumerate()
+            .map(|(j, source)| match (whole_input, source) {
+                (Some((input, index)), ColumnSource::Input(k)) => {
+                    let metadata = input.reader.metadata();
+                    let chunk = metadata.row_group(index).column(*k);
+                    let version = metadata.file_metadata().version();
+                    can_copy_chunk(chunk, version, &output_schema.column(j), options)
+                        .then_some(chunk)
+                }
+                _ => None,
+            })
+            .collect();
+
+        // With several threads the other columns are encoded in memory first and appended in
+        // order below.
+        let mut encoded = if options.threads > 1 {
+            encode_columns(
+                &mut copiers,
+                &raw_chunks,
+                &output_schema,
+                num_rows,
+                &masks,
+                options,
+            )?
+        } else {
+            Vec::new()
+        };
+
+        let mut row_group_writer = writer.next_row_group()?;
+
+        for (j, copier) in copiers.iter_mut().enumerate() {
+            if let (Some(chunk), Some((input, _)), Some(i), Some(copier)) =
+                (raw_chunks[j], whole_input, whole, copier.as_mut())
+            {
+                if options.verbose {
+                    eprintln!("writer: copying column chunk {j} as it is");
+                }
+
+                copier.skip_row_group(i, &masks)?;
+
+                let (column_index, offset_index) = read_page_index(input.chunks.as_ref(), chunk)?;
+                row_group_writer.append_column(
+                    input.chunks.as_ref(),
+                    ColumnCloseResult {
+                        bytes_written: u64::try_from(chunk.compressed_size())?,
+                        rows_written: u64::try_from(num_rows)?,
+                        metadata: chunk.clone(),
+                        bloom_filter: None,
+                        column_index,
+                        offset_index,
+                    },
+                )?;
+
+                report.chunks_copied += 1;
+                continue;
+            }
+
+            if let Some(column) = encoded.get_mut(j).and_then(Option::take) {
+                row_group_writer.append_column(&column.bytes, column.close)?;
+                report.add_column(&column.report);
+                continue;
+            }
+
+            let mut column_writer = row_group_writer.next_column()?.ok_or_else(|| {
+                anyhow!("Expected the writer to have a column for every column of the schema")
+            })?;
+
+            let column_report = match copier {
+                Some(copier) => copier.copy(column_writer.untyped(), num_rows, &masks)?,
+                None => write_nulls(column_writer.untyped(), &output_schema.column(j), num_rows)?,
+            };
+
+            column_writer.close()?;
+
+            report.add_column(&column_report);
+        }
+
+        let row_group_metadata = row_group_writer.close()?;
+
+        report.row_groups += 1;
+        report.columns = row_group_metadata.num_columns();
+        report.rows += row_group_metadata.num_rows();
+    }
+
+    for copier in copiers.iter_mut().flatten() {
+        copier.finish(&masks)?;
+    }
+
+    writer.close()?;
+
+    Ok(report)
+}
+
+/// A column chunk encoded in memory, ready to be appended to a row group.
+struct EncodedColumn {
+    bytes: Bytes,
+    close: ColumnCloseResult,
+    report: ColumnCopyReport,
+}
+
+/// Copies the next `num_rows` records of every column that has a copier but no raw chunk into
+/// column chunks in memory, spreading the columns over `options.threads` threads.
+fn encode_columns(
+    copiers: &mut [Option<Box<dyn ColumnCopier + '_>>],
+    raw_chunks: &[Option<&ColumnChunkMetaData>],
+    schema: &SchemaDescriptor,
+    num_rows: usize,
+    masks: &RowGroupMasks,
+    options: &RewriteOptions,
+) -> Result<Vec<Option<EncodedColumn>>> {
+    let mut encoded: Vec<_> = copiers.iter().map(|_| None).collect();
+
+    let jobs: Vec<_> = copiers
+        .iter_mut()
+        .enumerate()
+        .filter(|(j, _)| raw_chunks[*j].is_none())
+        .filter_map(|(j, copier)| Some((j, copier.as_mut()?)))
+        .collect();
+
+    let num_workers = options.threads.min(jobs.len());
+    let jobs = Mutex::new(jobs.into_iter());
+
+    let columns = thread::scope(|scope| {
+        let workers: Vec<_> = (0..num_workers)
+            .map(|_| {
+                scope.spawn(|| -> Result<Vec<(usize, EncodedColumn)>> {
+                    let mut columns = Vec::new();
+
+                    loop {
+                        let job = jobs
+                            .lock()
+                            .expect("no worker panics holding the lock")
+                            .next();
+                        let Some((j, copier)) = job else {
+                            return Ok(columns);
+                        };
+
+                        let column = encode_column(
+                            copier.as_mut(),
+                            schema.column(j),
+                            num_rows,
+                            masks,
+                            options,
+                        )?;
+                        columns.push((j, column));
+                    }
+                })
+            })
+            .collect();
+
+        workers
+            .into_iter()
+            .map(|worker| {
+                worker
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            })
+            .collect::<Result<Vec<_>>>()
+    })?;
+
+    for (j, column) in columns.into_iter().flatten() {
+        encoded[j] = Some(column);
+    }
+
+    Ok(encoded)
+}
+
+/// Copies the next `num_rows` records of `copier` into a column chunk in memory, described by
+/// `descr`.
+fn encode_column(
+    copier: &mut dyn ColumnCopier,
+    descr: ColumnDescPtr,
+    num_rows: usize,
+    masks: &RowGroupMasks,
+    options: &RewriteOptions,
+) -> Result<EncodedColumn> {
+    let mut buf = Vec::new();
+
+    let (close, report) = {
+        let mut sink = TrackedWrite::new(&mut buf);
+
+        let (close, report) = {
+            let page_writer = Box::new(SerializedPageWriter::new(&mut sink));
+            let mut column_writer =
+                get_column_writer(descr.clone(), options.properties.clone(), page_writer);
+
+            let report = copier.copy(&mut column_writer, num_rows, masks)?;
+            (close_column_writer(column_writer, &descr)?, report)
+        };
+
+        sink.flush()?;
+        (close, report)
+    };
+
+    Ok(EncodedColumn {
+        bytes: Bytes::from(buf),
+        close,
+        report,
+    })
+}
+
+/// Keeps the masks of the filtered row groups in `range`, evaluating those not held yet, and drops
+/// all others.
+fn update_masks(
+    masks: &mut RowGroupMasks,
+    row_groups: &[InputRowGroup],
+    range: RangeInclusive<usize>,
+    filter: &Predicate,
+    options: &RewriteOptions,
+) -> Result<()> {
+    masks.retain(|i, _| range.contains(i));
+
+    for i in range {
+        let input = &row_groups[i];
+
+        if input.filtered && input.num_kept > 0 && !masks.contains_key(&i) {
+            let row_group_reader = input.reader.get_row_group(input.index)?;
+            let num_rows = usize::try_from(row_group_reader.metadata().num_rows())?;
+
+            masks.insert(
+                i,
+                filter.evaluate(row_group_reader.as_ref(), num_rows, options)?,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// An input file, with the chunk reader kept around for copying column chunks as they are.
+struct Input<R: ChunkReader> {
+    chunks: Arc<R>,
+    reader: SerializedFileReader<SharedChunkReader<R>>,
+}
+
+/// A chunk reader shared between a file reader and the row group writers column chunks are
+/// copied into.
+pub(crate) struct SharedChunkReader<R>(pub(crate) Arc<R>);
+
+impl<R: ChunkReader> Length for SharedChunkReader<R> {
+    fn len(&self) -> u64 {
+        self.0.len()
+    }
+}
+
+impl<R: ChunkReader> ChunkReader for SharedChunkReader<R> {
+    type T = R::T;
+
+    fn get_read(&self, start: u64) -> parquet::errors::Result<Self::T> {
+        self.0.get_read(start)
+    }
+
+    fn get_bytes(&self, start: u64, length: usize) -> parquet::errors::Result<Bytes> {
+        self.0.get_bytes(start, length)
+    }
+}
+
+/// How an output row group is written.
+enum OutputRowGroup {
+    /// The next records read from the copiers.
+    Records(usize),
+    /// The input row group with this index, with column chunks copied as they are where
+    /// possible.
+    Whole(usize),
+}
+
+/// Whether `chunk`, from a file of format `version`, can be copied to a column described by
+/// `descr` without decoding it.
+fn can_copy_chunk(
+    chunk: &ColumnChunkMetaData,
+    version: i32,
+    descr: &ColumnDescriptor,
+    options: &RewriteOptions,
+) -> bool {
+    chunk.column_descr() == descr && chunk_matches_properties(chunk, version, &options.properties)
+}
+
+/// Reads the column index and offset index of `chunk` from `chunks`, the file it is in, so that
+/// they are written again for a copy of the chunk. The writer moves the page offsets along with
+/// the chunk.
+fn read_page_index<R: ChunkReader>(
+    chunks: &R,
+    chunk: &ColumnChunkMetaData,
+) -> Result<(Option<ColumnIndex>, Option<OffsetIndex>)> {
+    fn read<R: ChunkReader, T: TSerializable>(
+        chunks: &R,
+        offset: Option<i64>,
+        length: Option<i32>,
+    ) -> Result<Option<T>> {
+        let (Some(offset), Some(length)) = (offset, length) else {
+            return Ok(None);
+        };
+
+        let bytes = chunks.get_bytes(u64::try_from(offset)?, usize::try_from(length)?)?;
+        let mut protocol = TCompactInputProtocol::new(bytes.as_ref());
+
+        Ok(Some(T::read_from_in_protocol(&mut protocol)?))
+    }
+
+    Ok((
+        read(
+            chunks,
+            chunk.column_index_offset(),
+            chunk.column_index_length(),
+        )?,
+        read(
+            chunks,
+            chunk.offset_index_offset(),
+            chunk.offset_index_length(),
+        )?,
+    ))
+}
+
+/// Estimates how many rows make up `bytes`, from the uncompressed size of the copied columns in
+/// the input.
+pub(crate) fn rows_for_bytes(
+    row_groups: &[InputRowGroup],
+    sources: &[ColumnSource],
+    bytes: usize,
+) -> usize {
+    let mut total_bytes = 0;
+    let mut total_rows = 0;
+
+    for input in row_groups {
+        let row_group = input.reader.metadata().row_group(input.index);
+        total_rows += row_group.num_rows();
+
+        for source in sources {
+            if let ColumnSource::Input(j) = source {
+                total_bytes += row_group.column(*j).uncompressed_size();
+            }
+        }
+    }
+
+    if total_bytes <= 0 {
+        return usize::MAX;
+    }
+
+    (bytes as f64 * total_rows as f64 / total_bytes as f64) as usize
+}
+
+/// Splits `num_rows` into row groups of `rows_per_row_group`, the last one taking the rest.
+pub(crate) fn split_rows(num_rows: usize, rows_per_row_group: usize) -> Vec<usize> {
+    let mut sizes = vec![rows_per_row_group; num_rows / rows_per_row_group];
+
+    let rest = num_rows % rows_per_row_group;
+    if rest > 0 {
+        sizes.push(rest);
+    }
+
+    sizes
+}
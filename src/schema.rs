@@ -0,0 +1,541 @@
+//! Checking and deriving the schema of a rewritten file.
+
+use std::{str::FromStr, sync::Arc};
+
+use anyhow::{anyhow, bail, Error, Result};
+use parquet::{
+    basic::Repetition,
+    schema::types::{ColumnDescriptor, ColumnPath, SchemaDescriptor, Type, TypePtr},
+};
+
+/// A leaf column picked for a projection, parsed from either its index or its dotted path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColumnSelector {
+    Index(usize),
+    Path(ColumnPath),
+}
+
+impl FromStr for ColumnSelector {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            bail!("Expected a column index or path");
+        }
+
+        Ok(match s.parse() {
+            Ok(index) => ColumnSelector::Index(index),
+            Err(_) => {
+                ColumnSelector::Path(ColumnPath::new(s.split('.').map(str::to_owned).collect()))
+            }
+        })
+    }
+}
+
+/// The schema of a file rewritten from inputs of `input_schema`: `schema` if there is one, or
+/// else the input schema, with the leaf columns picked by `projection` if it is not empty.
+pub fn output_schema(
+    input_schema: &SchemaDescriptor,
+    schema: Option<&TypePtr>,
+    projection: &[ColumnSelector],
+) -> Result<TypePtr> {
+    let schema = schema
+        .cloned()
+        .unwrap_or_else(|| input_schema.root_schema_ptr());
+
+    if projection.is_empty() {
+        return Ok(schema);
+    }
+
+    project(&SchemaDescriptor::new(schema), projection)
+}
+
+/// Builds the schema containing only the leaf columns of `schema` picked by `columns`, keeping
+/// the groups they are nested in. Leaf columns keep their order in `schema`.
+pub fn project(schema: &SchemaDescriptor, columns: &[ColumnSelector]) -> Result<TypePtr> {
+    let mut selected = vec![false; schema.num_columns()];
+
+    for column in columns {
+        let index = match column {
+            ColumnSelector::Index(index) if *index < schema.num_columns() => *index,
+            ColumnSelector::Index(index) => bail!(
+                "Cannot project column {index}, the schema has {} leaf columns",
+                schema.num_columns()
+            ),
+            ColumnSelector::Path(path) => schema
+                .columns()
+                .iter()
+                .position(|descr| descr.path() == path)
+                .ok_or_else(|| {
+                    anyhow!("Cannot project {path}, the schema has no such leaf column")
+                })?,
+        };
+
+        selected[index] = true;
+    }
+
+    let mut next_leaf = 0;
+
+    match project_type(&schema.root_schema_ptr(), &selected, &mut next_leaf)? {
+        Some(projected) => Ok(projected),
+        None => bail!("A projection needs at least one column"),
+    }
+}
+
+/// Returns `tp` with all leaf columns not in `selected` removed, or `None` if none are left.
+/// `next_leaf` is the index of the first leaf column in `tp`.
+fn project_type(tp: &TypePtr, selected: &[bool], next_leaf: &mut usize) -> Result<Option<TypePtr>> {
+    if tp.is_primitive() {
+        *next_leaf += 1;
+        return Ok(selected[*next_leaf - 1].then(|| tp.clone()));
+    }
+
+    let mut fields = Vec::new();
+
+    for field in tp.get_fields() {
+        if let Some(field) = project_type(field, selected, next_leaf)? {
+            fields.push(field);
+        }
+    }
+
+    if fields.is_empty() {
+        return Ok(None);
+    }
+
+    // A field that lost leaf columns of its own is a new type, so the group only stays as it is
+    // when every field does.
+    let unchanged = fields.len() == tp.get_fields().len()
+        && fields
+            .iter()
+            .zip(tp.get_fields())
+            .all(|(field, original)| Arc::ptr_eq(field, original));
+
+    if unchanged {
+        return Ok(Some(tp.clone()));
+    }
+
+    let info = tp.get_basic_info();
+
+    let mut builder = Type::group_type_builder(info.name())
+        .with_converted_type(info.converted_type())
+        .with_logical_type(info.logical_type())
+        .with_id(info.has_id().then(|| info.id()))
+        .with_fields(fields);
+
+    if info.has_repetition() {
+        builder = builder.with_repetition(info.repetition());
+    }
+
+    Ok(Some(Arc::new(builder.build()?)))
+}
+
+/// Where the values of a leaf column of the rewritten file come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnSource {
+    /// Copied from the leaf column of the input with this index.
+    Input(usize),
+    /// The column is not in the input, every record is null.
+    Null,
+}
+
+/// Matches the leaf columns of `output` to the leaf columns of `input` by path, allowing the
+/// output schema to drop, add and reorder columns.
+///
+/// Input columns missing from `output` are dropped. Output columns missing from `input` are
+/// filled with nulls, so they need a nullable or repeated ancestor. A null record has no levels
+/// to line up with the lists of other columns and a null definition level marks its outermost
+/// optional field as null, so they must not share an optional or repeated group with a column
+/// copied from the input. Columns in both must agree on physical type, type length and
+/// maximum levels.
+pub fn map_columns(
+    input: &SchemaDescriptor,
+    output: &SchemaDescriptor,
+) -> Result<Vec<ColumnSource>> {
+    let sources = output
+        .columns()
+        .iter()
+        .map(|output_column| {
+            let found = input
+                .columns()
+                .iter()
+                .position(|input_column| input_column.path() == output_column.path());
+
+            match found {
+                Some(i) if same_layout(input.column(i).as_ref(), output_column) => {
+                    Ok(ColumnSource::Input(i))
+                }
+                Some(i) => bail!(
+                    "Schema is incompatible with the input file: column {} differs from {} in the input",
+                    describe(output_column),
+                    describe(&input.column(i))
+                ),
+                None if output_column.max_def_level() > 0 => Ok(ColumnSource::Null),
+                None => bail!(
+                    "Schema is incompatible with the input file: column {} is not in the input \
+                     and cannot be filled with nulls",
+                    output_column.path()
+                ),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let ancestors = outermost_nullable_ancestors(output);
+
+    for (i, source) in sources.iter().enumerate() {
+        let Some(ancestor) = &ancestors[i] else {
+            continue;
+        };
+
+        let shared = sources
+            .iter()
+            .zip(&ancestors)
+            .any(|(other, other_ancestor)| {
+                matches!(other, ColumnSource::Input(_)) && other_ancestor.as_ref() == Some(ancestor)
+            });
+
+        if *source == ColumnSource::Null && shared {
+            bail!(
+                "Schema is incompatible with the input file: column {} is not in the input and \
+                 cannot be filled with nulls, it shares the optional or repeated group {} with \
+                 columns of the input",
+                output.column(i).path(),
+                ancestor.join(".")
+            );
+        }
+    }
+
+    Ok(sources)
+}
+
+/// Returns the path of the outermost optional or repeated field above or at each leaf column of
+/// `schema`. Two leaf columns share such an ancestor exactly when they share this one.
+fn outermost_nullable_ancestors(schema: &SchemaDescriptor) -> Vec<Option<Vec<String>>> {
+    fn walk(
+        tp: &TypePtr,
+        path: &mut Vec<String>,
+        ancestor: Option<usize>,
+        ancestors: &mut Vec<Option<Vec<String>>>,
+    ) {
+        let info = tp.get_basic_info();
+        path.push(info.name().to_owned());
+
+        let nullable = info.has_repetition() && info.repetition() != Repetition::REQUIRED;
+        let ancestor = ancestor.or(nullable.then_some(path.len()));
+
+        if tp.is_primitive() {
+            ancestors.push(ancestor.map(|len| path[..len].to_vec()));
+        } else {
+            for field in tp.get_fields() {
+                walk(field, path, ancestor, ancestors);
+            }
+        }
+
+        path.pop();
+    }
+
+    let mut ancestors = Vec::with_capacity(schema.num_columns());
+
+    for field in schema.root_schema().get_fields() {
+        walk(field, &mut Vec::new(), None, &mut ancestors);
+    }
+
+    ancestors
+}
+
+/// Checks that values read from the leaf columns of `input` can be written to the leaf columns
+/// of `output` as they are: both must have the same number of leaf columns, and each pair must
+/// agree on physical type, type length and maximum levels. Names and logical types may differ.
+pub(crate) fn check_compatible(input: &SchemaDescriptor, output: &SchemaDescriptor) -> Result<()> {
+    if input.num_columns() != output.num_columns() {
+        bail!(
+            "Schema is incompatible with the input file: it has {} leaf columns but the input has {}",
+            output.num_columns(),
+            input.num_columns()
+        );
+    }
+
+    for (i, (input_column, output_column)) in
+        input.columns().iter().zip(output.columns()).enumerate()
+    {
+        if !same_layout(input_column, output_column) {
+            bail!(
+                "Schema is incompatible with the input file: leaf column {i} is {} but the input has {}",
+                describe(output_column),
+                describe(input_column)
+            );
+        }
+    }
+
+    Ok(())
+}
+
+fn same_layout(a: &ColumnDescriptor, b: &ColumnDescriptor) -> bool {
+    a.physical_type() == b.physical_type()
+        && a.type_length() == b.type_length()
+        && a.max_def_level() == b.max_def_level()
+        && a.max_rep_level() == b.max_rep_level()
+}
+
+fn describe(column: &ColumnDescriptor) -> String {
+    format!(
+        "{} ({}, max definition level {}, max repetition level {})",
+        column.path(),
+        column.physical_type(),
+        column.max_def_level(),
+        column.max_rep_level()
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::basic::{ConvertedType, LogicalType};
+
+    use super::*;
+    use crate::parse_schema;
+
+    const INPUT_SCHEMA: &str = "
+        message schema {
+            REQUIRED INT64 id;
+            REQUIRED GROUP names (LIST) {
+                REPEATED GROUP list {
+                    REQUIRED BYTE_ARRAY list_element (UTF8);
+                }
+            }
+            OPTIONAL BYTE_ARRAY city (UTF8);
+        }
+    ";
+
+    fn project_input(columns: &[&str]) -> Result<TypePtr> {
+        let input = SchemaDescriptor::new(Arc::new(parse_schema(INPUT_SCHEMA)));
+        let columns = columns
+            .iter()
+            .map(|column| column.parse())
+            .collect::<Result<Vec<ColumnSelector>>>()?;
+
+        project(&input, &columns)
+    }
+
+    fn map(output: &str) -> Result<Vec<ColumnSource>> {
+        map_from(INPUT_SCHEMA, output)
+    }
+
+    fn map_from(input: &str, output: &str) -> Result<Vec<ColumnSource>> {
+        let input = SchemaDescriptor::new(Arc::new(parse_schema(input)));
+        let output = SchemaDescriptor::new(Arc::new(parse_schema(output)));
+
+        map_columns(&input, &output)
+    }
+
+    #[test]
+    fn projections_pick_columns_by_index_and_path() {
+        let projected = project_input(&["city", "0", "0", "id"]);
+
+        assert_eq!(
+            *projected.unwrap(),
+            parse_schema(
+                "
+                message schema {
+                    REQUIRED INT64 id;
+                    OPTIONAL BYTE_ARRAY city (UTF8);
+                }
+                "
+            )
+        );
+    }
+
+    #[test]
+    fn unknown_and_missing_projected_columns_are_rejected() {
+        let error = project_input(&["id", "3"]).unwrap_err();
+        assert!(
+            error.to_string().contains("column 3"),
+            "unexpected error: {error}"
+        );
+
+        let error = project_input(&["names.list"]).unwrap_err();
+        assert!(
+            error.to_string().contains("names.list"),
+            "unexpected error: {error}"
+        );
+
+        assert!(project_input(&[]).is_err());
+    }
+
+    #[test]
+    fn projections_keep_the_groups_of_the_picked_columns() {
+        let input = SchemaDescriptor::new(Arc::new(parse_schema(
+            "
+            message schema {
+                OPTIONAL GROUP ranked (LIST) {
+                    REPEATED GROUP list {
+                        REQUIRED BYTE_ARRAY name (UTF8);
+                        REQUIRED INT32 rank;
+                    }
+                }
+                REQUIRED INT64 id;
+            }
+            ",
+        )));
+
+        let projected = project(&input, &["ranked.list.rank".parse().unwrap()]).unwrap();
+
+        assert_eq!(
+            *projected,
+            parse_schema(
+                "
+                message schema {
+                    OPTIONAL GROUP ranked (LIST) {
+                        REPEATED GROUP list {
+                            REQUIRED INT32 rank;
+                        }
+                    }
+                }
+                "
+            )
+        );
+
+        let ranked = projected.get_fields()[0].get_basic_info();
+        assert_eq!(ranked.converted_type(), ConvertedType::LIST);
+        assert_eq!(ranked.logical_type(), Some(LogicalType::List));
+        assert_eq!(ranked.repetition(), Repetition::OPTIONAL);
+    }
+
+    #[test]
+    fn columns_missing_from_the_output_are_dropped() {
+        let sources = map("
+            message schema {
+                REQUIRED INT64 id;
+                OPTIONAL BYTE_ARRAY city (UTF8);
+            }
+        ");
+
+        assert_eq!(
+            sources.unwrap(),
+            [ColumnSource::Input(0), ColumnSource::Input(2)]
+        );
+    }
+
+    #[test]
+    fn columns_are_matched_by_path_in_any_order() {
+        let sources = map("
+            message schema {
+                OPTIONAL BYTE_ARRAY city (UTF8);
+                REQUIRED GROUP names (LIST) {
+                    REPEATED GROUP list {
+                        REQUIRED BYTE_ARRAY list_element (UTF8);
+                    }
+                }
+                REQUIRED INT64 id;
+            }
+        ");
+
+        assert_eq!(
+            sources.unwrap(),
+            [
+                ColumnSource::Input(2),
+                ColumnSource::Input(1),
+                ColumnSource::Input(0)
+            ]
+        );
+    }
+
+    #[test]
+    fn nullable_columns_missing_from_the_input_are_filled_with_nulls() {
+        let sources = map("
+            message schema {
+                REQUIRED INT64 id;
+                OPTIONAL INT32 extra;
+                OPTIONAL GROUP tags (LIST) {
+                    REPEATED GROUP list {
+                        REQUIRED BYTE_ARRAY list_element (UTF8);
+                    }
+                }
+            }
+        ");
+
+        assert_eq!(
+            sources.unwrap(),
+            [
+                ColumnSource::Input(0),
+                ColumnSource::Null,
+                ColumnSource::Null
+            ]
+        );
+    }
+
+    #[test]
+    fn added_columns_cannot_share_a_repeated_group_with_copied_columns() {
+        let error = map("
+            message schema {
+                REQUIRED GROUP names (LIST) {
+                    REPEATED GROUP list {
+                        REQUIRED BYTE_ARRAY list_element (UTF8);
+                        OPTIONAL INT32 extra;
+                    }
+                }
+            }
+        ")
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("names.list.extra"),
+            "unexpected error: {error}"
+        );
+    }
+
+    #[test]
+    fn added_columns_cannot_share_an_optional_group_with_copied_columns() {
+        let input = "
+            message schema {
+                OPTIONAL GROUP address {
+                    OPTIONAL BYTE_ARRAY city (UTF8);
+                }
+                REQUIRED GROUP location {
+                    REQUIRED DOUBLE latitude;
+                }
+            }
+        ";
+
+        let error = map_from(
+            input,
+            "
+            message schema {
+                OPTIONAL GROUP address {
+                    OPTIONAL INT32 zip;
+                    OPTIONAL BYTE_ARRAY city (UTF8);
+                }
+            }
+            ",
+        )
+        .unwrap_err();
+
+        assert!(
+            error.to_string().contains("address.zip"),
+            "unexpected error: {error}"
+        );
+
+        // Below a required group the added column has an optional field of its own.
+        let sources = map_from(
+            input,
+            "
+            message schema {
+                REQUIRED GROUP location {
+                    REQUIRED DOUBLE latitude;
+                    OPTIONAL DOUBLE altitude;
+                }
+            }
+            ",
+        );
+
+        assert_eq!(
+            sources.unwrap(),
+            [ColumnSource::Input(1), ColumnSource::Null]
+        );
+    }
+
+    #[test]
+    fn required_and_mismatched_columns_are_rejected() {
+        assert!(map("message schema { REQUIRED INT32 extra; }").is_err());
+        assert!(map("message schema { REQUIRED INT32 id; }").is_err());
+        assert!(map("message schema { OPTIONAL INT64 id; }").is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Reading Parquet files from disk with positional reads.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use parquet::{
+    errors::Result,
+    file::reader::{ChunkReader, Length},
+};
+
+/// A file-backed [`ChunkReader`] that reads at explicit offsets instead of seeking.
+///
+/// `File` is a `ChunkReader` too, but the readers it hands out share one file cursor, so reading
+/// two column chunks at once interleaves their reads. Here every reader keeps its own position
+/// and only the bytes that are asked for are read, so memory stays bounded by what the caller
+/// holds on to.
+#[derive(Debug, Clone)]
+pub struct FileChunkReader {
+    file: Arc<File>,
+    len: u64,
+}
+
+impl FileChunkReader {
+    pub fn new(file: File) -> io::Result<Self> {
+        let len = file.metadata()?.len();
+
+        Ok(FileChunkReader {
+            file: Arc::new(file),
+            len,
+        })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl Length for FileChunkReader {
+    fn len(&self) -> u64 {
+        self.len
+    }
+}
+
+impl ChunkReader for FileChunkReader {
+    type T = BufReader<PositionalReader>;
+
+    fn get_read(&self, start: u64) -> Result<Self::T> {
+        Ok(BufReader::new(PositionalReader {
+            file: Arc::clone(&self.file),
+            position: start,
+        }))
+    }
+
+    fn get_bytes(&self, start: u64, length: usize) -> Result<Bytes> {
+        let mut buf = vec![0; length];
+        read_exact_at(&self.file, &mut buf, start)?;
+        Ok(buf.into())
+    }
+}
+
+/// Reads a file from a position of its own, see [`FileChunkReader`].
+#[derive(Debug)]
+pub struct PositionalReader {
+    file: Arc<File>,
+    position: u64,
+}
+
+impl Read for PositionalReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = read_at(&self.file, buf, self.position)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        match read_at(file, buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(read) => {
+                buf = &mut buf[read..];
+                offset += read as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(())
+}
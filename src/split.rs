@@ -0,0 +1,583 @@
+//! Splitting a Parquet file into several by row count or by the value of a column.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Context, Result};
+use parquet::{
+    basic::{ConvertedType, LogicalType, Type as PhysicalType},
+    column::reader::{get_typed_column_reader, ColumnReader, ColumnReaderImpl},
+    data_type::DataType,
+    file::{
+        reader::{ChunkReader, FileReader, RowGroupReader},
+        serialized_reader::SerializedFileReader,
+        writer::{SerializedColumnWriter, SerializedFileWriter},
+    },
+    schema::types::{ColumnDescriptor, ColumnPath, SchemaDescriptor},
+};
+
+use crate::{
+    filter::{AsScalar, Scalar},
+    records::{ColumnRecords, RecordReader},
+    with_data_type, RewriteOptions,
+};
+
+/// Directory name Hive uses for records whose partition column is null.
+const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// The most partitions a split by a column writes, as every one of them keeps a file open until
+/// the whole input is read. Hive limits dynamic partitions to the same number by default.
+pub const MAX_PARTITIONS: usize = 1000;
+
+/// How the records of a file are distributed to the parts it is split into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SplitBy {
+    /// Every part but the last holds this many records.
+    Rows(usize),
+    /// One part per distinct value of this leaf column, written to a Hive-style
+    /// `column=value/` directory.
+    Column(ColumnPath),
+}
+
+/// Summary of a finished split.
+#[derive(Debug, Default, Clone)]
+pub struct SplitReport {
+    /// The files written, in the order they were created.
+    pub files: Vec<PathBuf>,
+    pub row_groups: usize,
+    pub rows: usize,
+    pub values_written: usize,
+}
+
+/// Splits the Parquet file in `reader` into files below `directory`, reading every column chunk
+/// once, and the chunks of the partition column once more when splitting by a column.
+///
+/// Parts are named `part-00000.parquet`, `part-00001.parquet` and so on when splitting by rows,
+/// and `column=value/part-00000.parquet` when splitting by a column, which must hold at most one
+/// value per record, have at most [`MAX_PARTITIONS`] distinct values and be a boolean, integer or
+/// string column. Null and empty strings go to the
+/// `__HIVE_DEFAULT_PARTITION__` directory. Each row group of the input becomes a row group in every part that has
+/// records from it. Only the writer properties, batch size, level buffer capacity and verbosity
+/// of `options` are used.
+pub fn split<R>(
+    reader: R,
+    by: &SplitBy,
+    directory: &Path,
+    options: &RewriteOptions,
+) -> Result<SplitReport>
+where
+    R: ChunkReader + 'static,
+{
+    if options.batch_size == 0 {
+        bail!("The batch size must be at least 1");
+    }
+
+    let reader = SerializedFileReader::new(reader)?;
+    let schema = reader.metadata().file_metadata().schema_descr_ptr();
+
+    let key_column = match by {
+        SplitBy::Rows(0) => bail!("Cannot split into parts of 0 rows"),
+        SplitBy::Rows(_) => None,
+        SplitBy::Column(path) => {
+            let column = schema
+                .columns()
+                .iter()
+                .position(|descr| descr.path() == path)
+                .ok_or_else(|| {
+                    anyhow!("Cannot split by {path}, the schema has no such leaf column")
+                })?;
+
+            if schema.column(column).max_rep_level() > 0 {
+                bail!("Cannot split by {path}, it is repeated");
+            }
+
+            key_kind(&schema.column(column))?;
+
+            Some(column)
+        }
+    };
+
+    let mut parts: Vec<Part> = Vec::new();
+    let mut parts_by_key: HashMap<Option<String>, usize> = HashMap::new();
+    let mut report = SplitReport::default();
+
+    for i in 0..reader.num_row_groups() {
+        let row_group_reader = reader.get_row_group(i)?;
+        let num_rows = usize::try_from(row_group_reader.metadata().num_rows())?;
+
+        if options.verbose {
+            eprintln!("reader: reading row group {i}");
+        }
+
+        // The part each record of the row group goes to.
+        let assignment: Vec<usize> = match (by, key_column) {
+            (SplitBy::Column(path), Some(column)) => {
+                partition_keys(row_group_reader.as_ref(), column, num_rows, options)?
+                    .into_iter()
+                    .map(|key| {
+                        if let Some(part) = parts_by_key.get(&key) {
+                            return Ok(*part);
+                        }
+
+                        if parts.len() == MAX_PARTITIONS {
+                            bail!(
+                                "Cannot split by {path}, it has more than {MAX_PARTITIONS} \
+                                 distinct values"
+                            );
+                        }
+
+                        let directory = directory.join(partition_directory(path, &key));
+                        parts.push(Part::new(directory.join(part_file_name(0))));
+                        parts_by_key.insert(key, parts.len() - 1);
+                        Ok(parts.len() - 1)
+                    })
+                    .collect::<Result<_>>()?
+            }
+            (SplitBy::Rows(rows), _) => (report.rows..report.rows + num_rows)
+                .map(|row| {
+                    let part = row / rows;
+                    while parts.len() <= part {
+                        parts.push(Part::new(directory.join(part_file_name(parts.len()))));
+                    }
+                    part
+                })
+                .collect(),
+            (SplitBy::Column(_), None) => unreachable!("the key column was found above"),
+        };
+
+        let mut present = assignment.clone();
+        present.sort_unstable();
+        present.dedup();
+
+        for &part in &present {
+            if parts[part].writer.is_none() {
+                let path = parts[part].create(&schema, options)?;
+                report.files.push(path);
+            }
+        }
+
+        // Index into the row group writers of each part with records in this row group.
+        let mut slots = vec![usize::MAX; parts.len()];
+        for (slot, part) in present.iter().enumerate() {
+            slots[*part] = slot;
+        }
+        let record_slots: Vec<usize> = assignment.iter().map(|part| slots[*part]).collect();
+
+        let mut row_group_writers = Vec::with_capacity(present.len());
+
+        for (index, part) in parts.iter_mut().enumerate() {
+            if slots[index] != usize::MAX {
+                let writer = part
+                    .writer
+                    .as_mut()
+                    .expect("parts with records are created above");
+                row_group_writers.push(writer.next_row_group()?);
+            }
+        }
+
+        for j in 0..schema.num_columns() {
+            let mut column_writers = row_group_writers
+                .iter_mut()
+                .map(|row_group_writer| {
+                    row_group_writer.next_column()?.ok_or_else(|| {
+                        anyhow!(
+                            "Expected the writer to have a column for every column of the schema"
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            report.values_written += distribute_column(
+                row_group_reader.get_column_reader(j)?,
+                &schema.column(j),
+                &mut column_writers,
+                &record_slots,
+                options,
+            )?;
+
+            for column_writer in column_writers {
+                column_writer.close()?;
+            }
+        }
+
+        for row_group_writer in row_group_writers {
+            row_group_writer.close()?;
+            report.row_groups += 1;
+        }
+
+        report.rows += num_rows;
+
+        // Parts are filled in order when splitting by rows, so all but the last one are done.
+        if let (SplitBy::Rows(_), Some(last)) = (by, present.last()) {
+            for part in &mut parts[..*last] {
+                part.close()?;
+            }
+        }
+    }
+
+    for part in &mut parts {
+        part.close()?;
+    }
+
+    Ok(report)
+}
+
+/// A file the input is split into, created once it gets its first records.
+struct Part {
+    path: PathBuf,
+    writer: Option<SerializedFileWriter<BufWriter<File>>>,
+}
+
+impl Part {
+    fn new(path: PathBuf) -> Self {
+        Part { path, writer: None }
+    }
+
+    /// Creates the file of the part, and its directory, and returns its path.
+    fn create(&mut self, schema: &SchemaDescriptor, options: &RewriteOptions) -> Result<PathBuf> {
+        if let Some(directory) = self.path.parent() {
+            fs::create_dir_all(directory)
+                .with_context(|| format!("Failed to create {}", directory.display()))?;
+        }
+
+        if options.verbose {
+            eprintln!("writer: creating {}", self.path.display());
+        }
+
+        let file = File::create(&self.path)
+            .with_context(|| format!("Failed to create {}", self.path.display()))?;
+
+        self.writer = Some(SerializedFileWriter::new(
+            BufWriter::new(file),
+            schema.root_schema_ptr(),
+            options.properties.clone(),
+        )?);
+
+        Ok(self.path.clone())
+    }
+
+    /// Writes the footer of the file, if it was created and is still open.
+    fn close(&mut self) -> Result<()> {
+        if let Some(writer) = self.writer.take() {
+            writer.into_inner()?.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+fn part_file_name(part: usize) -> String {
+    format!("part-{part:05}.parquet")
+}
+
+/// The Hive-style directory of the records whose value of the column at `path` is `key`.
+fn partition_directory(path: &ColumnPath, key: &Option<String>) -> String {
+    let value = match key {
+        Some(key) => escape_partition_value(key),
+        None => NULL_PARTITION.to_owned(),
+    };
+
+    format!("{}={value}", escape_partition_value(&path.string()))
+}
+
+/// Percent-encodes the characters Hive escapes in partition directory names.
+fn escape_partition_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+
+    for c in value.chars() {
+        if c.is_control() || "\"#%'*/:=?\\{[]^".contains(c) {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                escaped.push_str(&format!("%{byte:02X}"));
+            }
+        } else {
+            escaped.push(c);
+        }
+    }
+
+    escaped
+}
+
+/// Reads the value of leaf column `column` for each of the `num_rows` records of a row group,
+/// `None` for records where it is null.
+fn partition_keys(
+    row_group_reader: &dyn RowGroupReader,
+    column: usize,
+    num_rows: usize,
+    options: &RewriteOptions,
+) -> Result<Vec<Option<String>>> {
+    let descr = row_group_reader.metadata().schema_descr().column(column);
+    let column_reader = row_group_reader.get_column_reader(column)?;
+
+    with_data_type!(descr.physical_type(), T => {
+        typed_partition_keys::<T>(column_reader, &descr, num_rows, options)
+    })
+}
+
+fn typed_partition_keys<T: DataType>(
+    column_reader: ColumnReader,
+    descr: &ColumnDescriptor,
+    num_rows: usize,
+    options: &RewriteOptions,
+) -> Result<Vec<Option<String>>>
+where
+    T::T: AsScalar,
+{
+    let kind = key_kind(descr)?;
+    let mut reader = RecordReader::new(
+        get_typed_column_reader::<T>(column_reader),
+        descr,
+        num_rows,
+        options,
+    );
+
+    let mut keys = Vec::with_capacity(num_rows);
+
+    while let Some(records) = reader.next_records()? {
+        for record in records.records() {
+            let key = match records.values[record.values].first() {
+                Some(value) => format_key(value.as_scalar(), kind, descr)?,
+                None => None,
+            };
+
+            keys.push(key);
+        }
+    }
+
+    if keys.len() != num_rows {
+        bail!(
+            "Column {} has {} records but the row group metadata says {num_rows}",
+            descr.path(),
+            keys.len()
+        );
+    }
+
+    Ok(keys)
+}
+
+/// How the values of a partition column are written in directory names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum KeyKind {
+    Boolean,
+    Signed,
+    /// Unsigned integers of the given bit width, stored as signed ones of the physical type.
+    Unsigned(u8),
+    Text,
+}
+
+/// The kind of the values of `descr`, if the split can use it as a partition column.
+///
+/// Only booleans, integers and strings are accepted: other types, like decimals, timestamps or
+/// raw bytes, have no text form that other readers of the directories would agree on.
+fn key_kind(descr: &ColumnDescriptor) -> Result<KeyKind> {
+    let kind = match (
+        descr.physical_type(),
+        descr.logical_type(),
+        descr.converted_type(),
+    ) {
+        (PhysicalType::BOOLEAN, None, ConvertedType::NONE) => Some(KeyKind::Boolean),
+        (PhysicalType::INT32 | PhysicalType::INT64, logical_type, converted_type) => {
+            match (logical_type, converted_type) {
+                (
+                    Some(LogicalType::Integer {
+                        is_signed: false,
+                        bit_width,
+                    }),
+                    _,
+                ) => Some(KeyKind::Unsigned(u8::try_from(bit_width)?)),
+                (Some(LogicalType::Integer { .. }), _) => Some(KeyKind::Signed),
+                (None, ConvertedType::UINT_8 | ConvertedType::UINT_16 | ConvertedType::UINT_32) => {
+                    Some(KeyKind::Unsigned(32))
+                }
+                (None, ConvertedType::UINT_64) => Some(KeyKind::Unsigned(64)),
+                (
+                    None,
+                    ConvertedType::NONE
+                    | ConvertedType::INT_8
+                    | ConvertedType::INT_16
+                    | ConvertedType::INT_32
+                    | ConvertedType::INT_64,
+                ) => Some(KeyKind::Signed),
+                _ => None,
+            }
+        }
+        (PhysicalType::BYTE_ARRAY, Some(LogicalType::String | LogicalType::Enum), _)
+        | (PhysicalType::BYTE_ARRAY, None, ConvertedType::UTF8 | ConvertedType::ENUM) => {
+            Some(KeyKind::Text)
+        }
+        _ => None,
+    };
+
+    kind.ok_or_else(|| {
+        anyhow!(
+            "Cannot split by {}, only boolean, integer and string columns can be partition columns",
+            descr.path()
+        )
+    })
+}
+
+/// The text of a partition key, `None` for the empty string, which Hive also writes to the
+/// default partition.
+fn format_key(
+    scalar: Option<Scalar<'_>>,
+    kind: KeyKind,
+    descr: &ColumnDescriptor,
+) -> Result<Option<String>> {
+    let key = match (scalar, kind) {
+        (Some(Scalar::Boolean(b)), KeyKind::Boolean) => b.to_string(),
+        (Some(Scalar::Integer(i)), KeyKind::Signed) => i.to_string(),
+        // 8, 16 and 32 bit unsigned integers are stored as INT32, 64 bit ones as INT64.
+        (Some(Scalar::Integer(i)), KeyKind::Unsigned(64)) => (i as u64).to_string(),
+        (Some(Scalar::Integer(i)), KeyKind::Unsigned(_)) => (i as i32 as u32).to_string(),
+        (Some(Scalar::Bytes(bytes)), KeyKind::Text) => std::str::from_utf8(bytes)
+            .with_context(|| format!("Cannot split by {}, a value is not UTF-8", descr.path()))?
+            .to_owned(),
+        _ => bail!(
+            "Cannot split by {}, its values do not match its type",
+            descr.path()
+        ),
+    };
+
+    Ok((!key.is_empty()).then_some(key))
+}
+
+/// Copies each record of a column chunk to the column writer at its index in `slots`, returning
+/// the number of values written.
+fn distribute_column(
+    column_reader: ColumnReader,
+    descr: &ColumnDescriptor,
+    column_writers: &mut [SerializedColumnWriter],
+    slots: &[usize],
+    options: &RewriteOptions,
+) -> Result<usize> {
+    with_data_type!(descr.physical_type(), T => distribute_typed_column(
+        get_typed_column_reader::<T>(column_reader),
+        descr,
+        column_writers,
+        slots,
+        options,
+    ))
+}
+
+fn distribute_typed_column<T: DataType>(
+    column_reader: ColumnReaderImpl<T>,
+    descr: &ColumnDescriptor,
+    column_writers: &mut [SerializedColumnWriter],
+    slots: &[usize],
+    options: &RewriteOptions,
+) -> Result<usize> {
+    let mut column_writers: Vec<_> = column_writers
+        .iter_mut()
+        .map(|column_writer| column_writer.typed::<T>())
+        .collect();
+
+    let mut reader = RecordReader::new(column_reader, descr, slots.len(), options);
+    let mut buffers: Vec<ColumnRecords<T>> = column_writers
+        .iter()
+        .map(|_| ColumnRecords::new(descr))
+        .collect();
+
+    let mut records_read = 0;
+    let mut values_written = 0;
+
+    while let Some(records) = reader.next_records()? {
+        for record in records.records() {
+            let slot = *slots.get(records_read).ok_or_else(|| {
+                anyhow!(
+                    "Column {} has more records than its row group",
+                    descr.path()
+                )
+            })?;
+
+            buffers[slot].push_record(&records, &record);
+            records_read += 1;
+        }
+
+        for (buffer, column_writer) in buffers.iter_mut().zip(&mut column_writers) {
+            values_written += buffer.write(column_writer)?;
+            buffer.clear();
+        }
+    }
+
+    if records_read != slots.len() {
+        bail!(
+            "Column {} has {records_read} records but the row group metadata says {}",
+            descr.path(),
+            slots.len()
+        );
+    }
+
+    Ok(values_written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partition_values_are_escaped_like_hive_does() {
+        assert_eq!(escape_partition_value("Oslo"), "Oslo");
+        assert_eq!(escape_partition_value("a/b=c"), "a%2Fb%3Dc");
+        assert_eq!(escape_partition_value("50% off?"), "50%25 off%3F");
+        assert_eq!(escape_partition_value("[x]:{y}"), "%5Bx%5D%3A%7By}");
+        assert_eq!(escape_partition_value("line\nbreak"), "line%0Abreak");
+        assert_eq!(escape_partition_value("Zürich"), "Zürich");
+        assert_eq!(escape_partition_value(""), "");
+    }
+
+    #[test]
+    fn null_values_go_to_the_default_partition() {
+        let path = ColumnPath::new(vec!["address".to_owned(), "city".to_owned()]);
+
+        assert_eq!(
+            partition_directory(&path, &Some("Lima".to_owned())),
+            "address.city=Lima"
+        );
+        assert_eq!(
+            partition_directory(&path, &None),
+            "address.city=__HIVE_DEFAULT_PARTITION__"
+        );
+    }
+
+    #[test]
+    fn partition_keys_are_formatted_by_their_type() -> Result<()> {
+        let schema = SchemaDescriptor::new(std::sync::Arc::new(crate::parse_schema(
+            "
+            message schema {
+                REQUIRED BOOLEAN flag;
+                REQUIRED INT32 small (UINT_8);
+                REQUIRED INT64 big (INTEGER(64, false));
+                REQUIRED INT64 id;
+                REQUIRED BYTE_ARRAY city (UTF8);
+                REQUIRED BYTE_ARRAY blob;
+                REQUIRED INT32 price (DECIMAL(9, 2));
+                REQUIRED INT64 at (TIMESTAMP_MILLIS);
+                REQUIRED DOUBLE ratio;
+            }
+            ",
+        )));
+        let column = |i| schema.column(i);
+        let key = |i, scalar| format_key(Some(scalar), key_kind(&column(i))?, &column(i));
+
+        assert_eq!(key(0, Scalar::Boolean(true))?.as_deref(), Some("true"));
+        assert_eq!(key(1, Scalar::Integer(-1))?.as_deref(), Some("4294967295"));
+        assert_eq!(
+            key(2, Scalar::Integer(-1))?.as_deref(),
+            Some("18446744073709551615")
+        );
+        assert_eq!(key(3, Scalar::Integer(-1))?.as_deref(), Some("-1"));
+        assert_eq!(key(4, Scalar::Bytes(b"Lima"))?.as_deref(), Some("Lima"));
+        assert_eq!(key(4, Scalar::Bytes(b""))?, None);
+        assert!(key(4, Scalar::Bytes(b"\xff")).is_err());
+
+        for i in 5..9 {
+            assert!(key_kind(&column(i)).is_err(), "{}", column(i).path());
+        }
+
+        Ok(())
+    }
+}
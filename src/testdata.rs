@@ -0,0 +1,1016 @@
+//! In-memory test data for exercising the reader and the rewriter.
+
+use std::{fmt, io::Write, marker::PhantomData, sync::Arc};
+
+use anyhow::{anyhow, bail, Result};
+use bytes::{BufMut, Bytes, BytesMut};
+use parquet::{
+    basic::Repetition,
+    column::writer::{get_typed_column_writer_mut, ColumnWriter, ColumnWriterImpl},
+    data_type::{ByteArray, ByteArrayType, DataType, FixedLenByteArray, Int96},
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::types::{ColumnDescPtr, SchemaDescriptor, Type, TypePtr},
+};
+
+use crate::parse_schema;
+
+/// A single required list of strings, the schema the reader issues were found with.
+pub const NAMES_SCHEMA: &str = "
+message schema {
+    REQUIRED GROUP names (LIST) {
+        REPEATED GROUP list {
+            REQUIRED BYTE_ARRAY list_element (UTF8);
+        }
+    }
+}
+";
+
+/// The list of [`NAMES_SCHEMA`] with both the list and its elements optional, so a record can
+/// be a null list, an empty list or a list with null elements.
+pub const NULLABLE_NAMES_SCHEMA: &str = "
+message schema {
+    OPTIONAL GROUP names (LIST) {
+        REPEATED GROUP list {
+            OPTIONAL BYTE_ARRAY list_element (UTF8);
+        }
+    }
+}
+";
+
+pub fn create_small_parquet_file(
+    schema: Arc<parquet::schema::types::Type>,
+    props: Arc<WriterProperties>,
+) -> Result<Bytes> {
+    let mut repeated_writer = RepeatedWriter::new();
+
+    repeated_writer.push(names(4).into_iter());
+    repeated_writer.push(names(4).into_iter());
+
+    create_parquet_file(schema, props, &repeated_writer)
+}
+
+/// Writes a null list, an empty list, lists with null elements and a full list, for a schema like
+/// [`NULLABLE_NAMES_SCHEMA`].
+pub fn create_nullable_parquet_file(
+    schema: Arc<parquet::schema::types::Type>,
+    props: Arc<WriterProperties>,
+) -> Result<Bytes> {
+    let mut repeated_writer =
+        RepeatedWriter::<ByteArrayType>::for_column(&SchemaDescriptor::new(schema.clone()), 0);
+
+    repeated_writer.push_null();
+    repeated_writer.push(Vec::<Vec<u8>>::new().into_iter());
+    repeated_writer.push_with_null_elements([None::<Vec<u8>>].into_iter());
+    repeated_writer.push_with_null_elements(
+        names(3)
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| (i != 1).then_some(name)),
+    );
+    repeated_writer.push(names(4).into_iter());
+
+    create_parquet_file(schema, props, &repeated_writer)
+}
+
+/// Writes the records collected in `repeated_writer` as a file with a single row group, for a
+/// schema with a single leaf column.
+pub fn create_parquet_file<T: DataType>(
+    schema: Arc<parquet::schema::types::Type>,
+    props: Arc<WriterProperties>,
+    repeated_writer: &RepeatedWriter<T>,
+) -> Result<Bytes> {
+    let sink = write_parquet_file(BytesMut::new().writer(), schema, props, repeated_writer)?;
+
+    Ok(Bytes::from(sink.into_inner()))
+}
+
+/// Like [`create_parquet_file`], but streams the file to `sink` instead of collecting it in
+/// memory, and returns `sink` once the footer is written.
+pub fn write_parquet_file<W: Write + Send, T: DataType>(
+    sink: W,
+    schema: Arc<parquet::schema::types::Type>,
+    props: Arc<WriterProperties>,
+    repeated_writer: &RepeatedWriter<T>,
+) -> Result<W> {
+    let mut writer = SerializedFileWriter::new(sink, schema, props)?;
+
+    {
+        let mut row_group_writer = writer.next_row_group()?;
+
+        let mut column_writer = row_group_writer
+            .next_column()?
+            .ok_or(anyhow!("No column"))?;
+
+        repeated_writer.write(column_writer.typed::<T>())?;
+
+        column_writer.close()?;
+
+        row_group_writer.close()?;
+    }
+
+    Ok(writer.into_inner()?)
+}
+
+/// Writes a file with one row group for each entry of `row_groups`, which holds the records of
+/// every leaf column of `schema` in order.
+pub fn create_row_groups_file(
+    schema: Arc<parquet::schema::types::Type>,
+    props: Arc<WriterProperties>,
+    row_groups: &[Vec<&dyn TestColumn>],
+) -> Result<Bytes> {
+    let mut writer = SerializedFileWriter::new(BytesMut::new().writer(), schema, props)?;
+
+    for columns in row_groups {
+        let mut row_group_writer = writer.next_row_group()?;
+
+        for column in columns {
+            let mut column_writer = row_group_writer
+                .next_column()?
+                .ok_or(anyhow!("No column"))?;
+
+            column.write_column(column_writer.untyped())?;
+
+            column_writer.close()?;
+        }
+
+        row_group_writer.close()?;
+    }
+
+    Ok(Bytes::from(writer.into_inner()?.into_inner()))
+}
+
+/// Like [`create_row_groups_file`], but builds the leaf columns of each row group with `columns`,
+/// given the schema and the entry of `row_groups` for the row group.
+pub fn create_records_file<R>(
+    schema: Arc<parquet::schema::types::Type>,
+    props: Arc<WriterProperties>,
+    row_groups: &[R],
+    mut columns: impl FnMut(&SchemaDescriptor, &R) -> Result<Vec<Box<dyn TestColumn>>>,
+) -> Result<Bytes> {
+    let descr = SchemaDescriptor::new(schema.clone());
+
+    let columns = row_groups
+        .iter()
+        .map(|records| columns(&descr, records))
+        .collect::<Result<Vec<_>>>()?;
+
+    let row_groups: Vec<Vec<&dyn TestColumn>> = columns
+        .iter()
+        .map(|columns| columns.iter().map(AsRef::as_ref).collect())
+        .collect();
+
+    create_row_groups_file(schema, props, &row_groups)
+}
+
+/// The records of a leaf column of any physical type, see [`create_row_groups_file`].
+pub trait TestColumn {
+    /// Writes the records to `column_writer` and returns the number of values written.
+    fn write_column(&self, column_writer: &mut ColumnWriter) -> Result<usize>;
+}
+
+impl<T: DataType> TestColumn for RepeatedWriter<T> {
+    fn write_column(&self, column_writer: &mut ColumnWriter) -> Result<usize> {
+        self.write(get_typed_column_writer_mut::<T>(column_writer))
+    }
+}
+
+/// A value of a leaf column and the fields on its path, as written by
+/// [`RepeatedWriter::push_value`].
+///
+/// Groups on the path of the column are transparent: a value that is not a [`Value::Group`] is
+/// handed on to the child field as it is, so a three-level list is simply a [`Value::List`] of its
+/// elements.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value<V = ByteArray> {
+    /// A missing value of the closest optional field.
+    Null,
+    /// A value of the leaf column, of its physical type.
+    Leaf(V),
+    /// The elements of a repeated field.
+    List(Vec<Value<V>>),
+    /// The fields of a group by name. Only the field on the path of the column is written, a
+    /// missing one is null, or empty if it is repeated.
+    Group(Vec<(String, Value<V>)>),
+}
+
+impl<V> Value<V> {
+    pub fn group<'a>(fields: impl IntoIterator<Item = (&'a str, Value<V>)>) -> Self {
+        Value::Group(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// The value of `field` when it is missing from a group.
+    fn missing(field: &Type) -> Self {
+        match field.get_basic_info().repetition() {
+            Repetition::REPEATED => Value::List(Vec::new()),
+            _ => Value::Null,
+        }
+    }
+}
+
+/// Conversion of nested Rust values into a [`Value`] with leaves of type `V`: `Option` for
+/// optional fields, `Vec` for repeated ones. Structs implement it with [`Value::group`].
+pub trait IntoValue<V> {
+    fn into_value(self) -> Value<V>;
+}
+
+impl<V> IntoValue<V> for Value<V> {
+    fn into_value(self) -> Value<V> {
+        self
+    }
+}
+
+impl<V, T: IntoValue<V>> IntoValue<V> for Option<T> {
+    fn into_value(self) -> Value<V> {
+        self.map_or(Value::Null, IntoValue::into_value)
+    }
+}
+
+impl<V, T: IntoValue<V>> IntoValue<V> for Vec<T> {
+    fn into_value(self) -> Value<V> {
+        Value::List(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+macro_rules! leaf_values {
+    ($($leaf:ty),*) => {
+        $(
+            impl IntoValue<$leaf> for $leaf {
+                fn into_value(self) -> Value<$leaf> {
+                    Value::Leaf(self)
+                }
+            }
+        )*
+    };
+}
+
+leaf_values!(
+    bool,
+    i32,
+    i64,
+    Int96,
+    f32,
+    f64,
+    ByteArray,
+    FixedLenByteArray
+);
+
+impl IntoValue<ByteArray> for &str {
+    fn into_value(self) -> Value {
+        Value::Leaf(self.into())
+    }
+}
+
+impl IntoValue<ByteArray> for String {
+    fn into_value(self) -> Value {
+        Value::Leaf(self.into_bytes().into())
+    }
+}
+
+/// Collects the values, definition levels and repetition levels of records of one leaf column
+/// of physical type `T`, ready to be passed to `write_batch`.
+pub struct RepeatedWriter<T: DataType = ByteArrayType> {
+    descr: ColumnDescPtr,
+    /// The fields from the root of the schema down to the leaf column.
+    path: Vec<TypePtr>,
+    values: Vec<T::T>,
+    def_levels: Vec<i16>,
+    rep_levels: Vec<i16>,
+}
+
+impl<T: DataType> fmt::Debug for RepeatedWriter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RepeatedWriter")
+            .field("column", &self.descr.path())
+            .field("values", &self.values)
+            .field("def_levels", &self.def_levels)
+            .field("rep_levels", &self.rep_levels)
+            .finish()
+    }
+}
+
+impl Default for RepeatedWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RepeatedWriter {
+    /// A writer for the leaf column of [`NAMES_SCHEMA`].
+    pub fn new() -> Self {
+        let schema = SchemaDescriptor::new(Arc::new(parse_schema(NAMES_SCHEMA)));
+        Self::for_column(&schema, 0)
+    }
+}
+
+impl<T: DataType> RepeatedWriter<T> {
+    /// A writer for leaf column `column` of `schema`.
+    ///
+    /// Panics if the physical type of the column is not that of `T`.
+    pub fn for_column(schema: &SchemaDescriptor, column: usize) -> Self {
+        let (descr, path) = column_path::<T>(schema, column);
+
+        RepeatedWriter {
+            descr,
+            path,
+            values: Default::default(),
+            def_levels: Default::default(),
+            rep_levels: Default::default(),
+        }
+    }
+
+    pub fn descr(&self) -> &ColumnDescPtr {
+        &self.descr
+    }
+
+    /// Adds a record holding a list of `values`, for a column with a single repeated field.
+    ///
+    /// Panics if the column is not a list.
+    pub fn push<Iter: ExactSizeIterator<Item = V>, V>(&mut self, values: Iter)
+    where
+        V: Into<T::T>,
+    {
+        let values = values.map(|val| Value::Leaf(val.into())).collect();
+
+        self.push_value(Value::List(values))
+            .expect("a list of values for a column with a single repeated field");
+    }
+
+    /// Adds a null record. In a three-level list whose list field is optional, this is a null
+    /// list, definition level 0, as opposed to an empty list, which defines the list field.
+    ///
+    /// Panics if no field on the path of the column is optional.
+    pub fn push_null(&mut self) {
+        self.push_value(Value::Null)
+            .expect("an optional field on the path of the column");
+    }
+
+    /// Like [`push`](Self::push), but `None` elements are written as null elements, one
+    /// definition level below the values.
+    ///
+    /// Panics if the column is not a list with optional elements.
+    pub fn push_with_null_elements<Iter: ExactSizeIterator<Item = Option<V>>, V>(
+        &mut self,
+        values: Iter,
+    ) where
+        V: Into<T::T>,
+    {
+        let values = values
+            .map(|val| val.map_or(Value::Null, |val| Value::Leaf(val.into())))
+            .collect();
+
+        self.push_value(Value::List(values))
+            .expect("a list of values for a column with optional elements");
+    }
+
+    /// Adds a record, computing the levels of every value from the fields on the path of the
+    /// column. Fails, leaving the writer as it was, if `value` does not fit them.
+    pub fn push_value(&mut self, value: impl IntoValue<T::T>) -> Result<()> {
+        let num_values = self.values.len();
+        let num_levels = self.def_levels.len();
+
+        let pushed = self.push_field(0, value.into_value(), 0, 0, 0);
+
+        if pushed.is_err() {
+            self.values.truncate(num_values);
+            self.def_levels.truncate(num_levels);
+            self.rep_levels.truncate(num_levels);
+        }
+
+        pushed
+    }
+
+    /// Pushes `value` for the field at `depth` of the path, given the definition level of its
+    /// parent, the repetition level of its first leaf and the repetition level of the closest
+    /// repeated field above it.
+    fn push_field(
+        &mut self,
+        depth: usize,
+        value: Value<T::T>,
+        def_level: i16,
+        rep_level: i16,
+        list_rep_level: i16,
+    ) -> Result<()> {
+        let field = Arc::clone(&self.path[depth]);
+
+        match field.get_basic_info().repetition() {
+            Repetition::REQUIRED => {
+                self.push_content(depth, value, def_level, rep_level, list_rep_level)
+            }
+            Repetition::OPTIONAL => match value {
+                Value::Null => {
+                    self.push_levels(def_level, rep_level);
+                    Ok(())
+                }
+                value => self.push_content(depth, value, def_level + 1, rep_level, list_rep_level),
+            },
+            Repetition::REPEATED => {
+                let Value::List(elements) = value else {
+                    bail!(
+                        "Expected a list for repeated field {}, got {value:?}",
+                        field.name()
+                    );
+                };
+
+                if elements.is_empty() {
+                    self.push_levels(def_level, rep_level);
+                    return Ok(());
+                }
+
+                let list_rep_level = list_rep_level + 1;
+
+                for (i, element) in elements.into_iter().enumerate() {
+                    let rep_level = if i == 0 { rep_level } else { list_rep_level };
+                    self.push_content(depth, element, def_level + 1, rep_level, list_rep_level)?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// Pushes `value` for the content of the field at `depth`, once it is known to be defined.
+    fn push_content(
+        &mut self,
+        depth: usize,
+        value: Value<T::T>,
+        def_level: i16,
+        rep_level: i16,
+        list_rep_level: i16,
+    ) -> Result<()> {
+        let Some(child) = self.path.get(depth + 1) else {
+            let Value::Leaf(leaf) = value else {
+                bail!(
+                    "Expected a value for column {}, got {value:?}",
+                    self.descr.path()
+                );
+            };
+
+            self.values.push(leaf);
+            self.push_levels(def_level, rep_level);
+            return Ok(());
+        };
+
+        let value = match value {
+            Value::Group(fields) => fields
+                .into_iter()
+                .find(|(name, _)| name == child.name())
+                .map_or_else(|| Value::missing(child), |(_, value)| value),
+            value => value,
+        };
+
+        self.push_field(depth + 1, value, def_level, rep_level, list_rep_level)
+    }
+
+    fn push_levels(&mut self, def_level: i16, rep_level: i16) {
+        self.def_levels.push(def_level);
+        self.rep_levels.push(rep_level);
+    }
+
+    pub fn values(&self) -> &[T::T] {
+        &self.values
+    }
+
+    /// The definition levels, or `None` for a column without optional or repeated fields.
+    pub fn def_levels(&self) -> Option<&[i16]> {
+        (self.descr.max_def_level() > 0).then_some(&self.def_levels)
+    }
+
+    /// The repetition levels, or `None` for a column without repeated fields.
+    pub fn rep_levels(&self) -> Option<&[i16]> {
+        (self.descr.max_rep_level() > 0).then_some(&self.rep_levels)
+    }
+
+    /// Writes the collected records to `column_writer` and returns the number of values written.
+    pub fn write(&self, column_writer: &mut ColumnWriterImpl<T>) -> Result<usize> {
+        Ok(column_writer.write_batch(&self.values, self.def_levels(), self.rep_levels())?)
+    }
+}
+
+/// Reassembles the records of one leaf column of physical type `T` from the values and levels
+/// returned by `read_records`, the inverse of [`RepeatedWriter`].
+///
+/// Records come out as a [`Value`] each, with the groups on the path of the column left out: a
+/// null where a field is undefined, a [`Value::List`] for every repeated field and a
+/// [`Value::Leaf`] for every value.
+pub struct RecordAssembler<T: DataType = ByteArrayType> {
+    descr: ColumnDescPtr,
+    /// The fields from the root of the schema down to the leaf column.
+    path: Vec<TypePtr>,
+    _type: PhantomData<T>,
+}
+
+impl<T: DataType> RecordAssembler<T> {
+    /// An assembler for leaf column `column` of `schema`.
+    ///
+    /// Panics if the physical type of the column is not that of `T`.
+    pub fn for_column(schema: &SchemaDescriptor, column: usize) -> Self {
+        let (descr, path) = column_path::<T>(schema, column);
+
+        RecordAssembler {
+            descr,
+            path,
+            _type: PhantomData,
+        }
+    }
+
+    /// Reassembles the records whose values and levels are given. The levels must cover whole
+    /// records. Levels of a column without optional or repeated fields may be left out.
+    pub fn assemble(
+        &self,
+        values: &[T::T],
+        def_levels: Option<&[i16]>,
+        rep_levels: Option<&[i16]>,
+    ) -> Result<Vec<Value<T::T>>> {
+        if def_levels.is_none() && self.descr.max_def_level() > 0 {
+            bail!(
+                "Column {} has optional or repeated fields and needs definition levels",
+                self.descr.path()
+            );
+        }
+
+        if rep_levels.is_none() && self.descr.max_rep_level() > 0 {
+            bail!(
+                "Column {} has repeated fields and needs repetition levels",
+                self.descr.path()
+            );
+        }
+
+        let num_levels = def_levels.or(rep_levels).map_or(values.len(), <[i16]>::len);
+
+        if rep_levels.is_some_and(|rep_levels| rep_levels.len() != num_levels) {
+            bail!("Expected as many repetition levels as definition levels");
+        }
+
+        let mut levels = Levels {
+            values,
+            def_levels,
+            rep_levels,
+            num_levels,
+            level: 0,
+            value: 0,
+        };
+
+        let mut records = Vec::new();
+
+        while levels.level < num_levels {
+            if levels.rep_level() != 0 {
+                bail!(
+                    "Expected a record to start at level {}, found repetition level {}",
+                    levels.level,
+                    levels.rep_level()
+                );
+            }
+
+            records.push(self.assemble_field(&mut levels, 0, 0, 0)?);
+        }
+
+        if levels.value != values.len() {
+            bail!(
+                "Only {} of {} values belong to the records",
+                levels.value,
+                values.len()
+            );
+        }
+
+        Ok(records)
+    }
+
+    /// Like [`assemble`](Self::assemble), converting every record with [`FromValue`].
+    pub fn assemble_into<R: FromValue<T::T>>(
+        &self,
+        values: &[T::T],
+        def_levels: Option<&[i16]>,
+        rep_levels: Option<&[i16]>,
+    ) -> Result<Vec<R>> {
+        self.assemble(values, def_levels, rep_levels)?
+            .into_iter()
+            .map(R::from_value)
+            .collect()
+    }
+
+    /// Reads the value of the field at `depth` of the path, given the definition level of its
+    /// parent and the repetition level of the closest repeated field above it.
+    fn assemble_field(
+        &self,
+        levels: &mut Levels<T::T>,
+        depth: usize,
+        def_level: i16,
+        list_rep_level: i16,
+    ) -> Result<Value<T::T>> {
+        match self.path[depth].get_basic_info().repetition() {
+            Repetition::REQUIRED => self.assemble_content(levels, depth, def_level, list_rep_level),
+            Repetition::OPTIONAL => {
+                if levels.def_level() <= def_level {
+                    levels.level += 1;
+                    return Ok(Value::Null);
+                }
+
+                self.assemble_content(levels, depth, def_level + 1, list_rep_level)
+            }
+            Repetition::REPEATED => {
+                if levels.def_level() <= def_level {
+                    levels.level += 1;
+                    return Ok(Value::List(Vec::new()));
+                }
+
+                let list_rep_level = list_rep_level + 1;
+                let mut elements = Vec::new();
+
+                loop {
+                    elements.push(self.assemble_content(
+                        levels,
+                        depth,
+                        def_level + 1,
+                        list_rep_level,
+                    )?);
+
+                    if levels.level == levels.num_levels || levels.rep_level() < list_rep_level {
+                        return Ok(Value::List(elements));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Reads the content of the field at `depth`, once it is known to be defined.
+    fn assemble_content(
+        &self,
+        levels: &mut Levels<T::T>,
+        depth: usize,
+        def_level: i16,
+        list_rep_level: i16,
+    ) -> Result<Value<T::T>> {
+        if depth + 1 < self.path.len() {
+            return self.assemble_field(levels, depth + 1, def_level, list_rep_level);
+        }
+
+        let Some(value) = levels.values.get(levels.value) else {
+            bail!(
+                "Column {} has more defined levels than values",
+                self.descr.path()
+            );
+        };
+
+        levels.level += 1;
+        levels.value += 1;
+
+        Ok(Value::Leaf(value.clone()))
+    }
+}
+
+/// The position of a [`RecordAssembler`] in the values and levels it reads.
+struct Levels<'a, V> {
+    values: &'a [V],
+    def_levels: Option<&'a [i16]>,
+    rep_levels: Option<&'a [i16]>,
+    num_levels: usize,
+    level: usize,
+    value: usize,
+}
+
+impl<V> Levels<'_, V> {
+    fn def_level(&self) -> i16 {
+        self.def_levels.map_or(0, |levels| levels[self.level])
+    }
+
+    fn rep_level(&self) -> i16 {
+        self.rep_levels.map_or(0, |levels| levels[self.level])
+    }
+}
+
+/// Conversion of a [`Value`] with leaves of type `V` into nested Rust values, the inverse of
+/// [`IntoValue`].
+pub trait FromValue<V>: Sized {
+    fn from_value(value: Value<V>) -> Result<Self>;
+}
+
+impl<V> FromValue<V> for Value<V> {
+    fn from_value(value: Value<V>) -> Result<Self> {
+        Ok(value)
+    }
+}
+
+impl<V, T: FromValue<V>> FromValue<V> for Option<T> {
+    fn from_value(value: Value<V>) -> Result<Self> {
+        match value {
+            Value::Null => Ok(None),
+            value => Ok(Some(T::from_value(value)?)),
+        }
+    }
+}
+
+impl<V: fmt::Debug, T: FromValue<V>> FromValue<V> for Vec<T> {
+    fn from_value(value: Value<V>) -> Result<Self> {
+        match value {
+            Value::List(elements) => elements.into_iter().map(T::from_value).collect(),
+            value => bail!("Expected a list, got {value:?}"),
+        }
+    }
+}
+
+macro_rules! from_leaf_values {
+    ($($leaf:ty),*) => {
+        $(
+            impl FromValue<$leaf> for $leaf {
+                fn from_value(value: Value<$leaf>) -> Result<Self> {
+                    match value {
+                        Value::Leaf(leaf) => Ok(leaf),
+                        value => bail!("Expected a value, got {value:?}"),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+from_leaf_values!(
+    bool,
+    i32,
+    i64,
+    Int96,
+    f32,
+    f64,
+    ByteArray,
+    FixedLenByteArray
+);
+
+impl FromValue<ByteArray> for String {
+    fn from_value(value: Value<ByteArray>) -> Result<Self> {
+        Ok(ByteArray::from_value(value)?.as_utf8()?.to_owned())
+    }
+}
+
+/// Returns the descriptor of leaf column `column` of `schema` and the fields from the root of the
+/// schema down to it.
+///
+/// Panics if the physical type of the column is not that of `T`.
+fn column_path<T: DataType>(
+    schema: &SchemaDescriptor,
+    column: usize,
+) -> (ColumnDescPtr, Vec<TypePtr>) {
+    let descr = schema.column(column);
+
+    assert_eq!(
+        descr.physical_type(),
+        T::get_physical_type(),
+        "physical type of column {}",
+        descr.path()
+    );
+
+    let mut fields = schema.root_schema().get_fields();
+    let mut path = Vec::new();
+
+    for part in descr.path().parts() {
+        let field = fields
+            .iter()
+            .find(|field| field.name() == part)
+            .expect("the path of a leaf column leads to it");
+
+        path.push(Arc::clone(field));
+
+        if field.is_group() {
+            fields = field.get_fields();
+        }
+    }
+
+    (descr, path)
+}
+
+pub fn names(count: usize) -> Vec<Vec<u8>> {
+    (0..count)
+        .map(|i| format!("Name {i}").into_bytes())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use parquet::data_type::Int32Type;
+
+    use super::*;
+
+    const MATRIX_SCHEMA: &str = "
+        message schema {
+            REQUIRED GROUP matrix (LIST) {
+                REPEATED GROUP list {
+                    REQUIRED GROUP row (LIST) {
+                        REPEATED GROUP list {
+                            REQUIRED INT32 cell;
+                        }
+                    }
+                }
+            }
+        }
+    ";
+
+    fn assembler<T: DataType>(schema: &str, column: usize) -> RecordAssembler<T> {
+        let schema = SchemaDescriptor::new(Arc::new(parse_schema(schema)));
+        RecordAssembler::for_column(&schema, column)
+    }
+
+    #[test]
+    fn nested_lists_are_reassembled_from_their_levels() -> Result<()> {
+        let records = vec![vec![vec![1, 2], vec![]], vec![], vec![vec![3]]];
+        let def_levels = [2, 2, 1, 0, 2];
+        let rep_levels = [0, 2, 1, 0, 0];
+
+        let schema = SchemaDescriptor::new(Arc::new(parse_schema(MATRIX_SCHEMA)));
+        let mut repeated_writer = RepeatedWriter::<Int32Type>::for_column(&schema, 0);
+        for record in &records {
+            repeated_writer.push_value(record.clone())?;
+        }
+
+        assert_eq!(repeated_writer.values(), [1, 2, 3]);
+        assert_eq!(repeated_writer.def_levels(), Some(&def_levels[..]));
+        assert_eq!(repeated_writer.rep_levels(), Some(&rep_levels[..]));
+
+        let assembled = assembler::<Int32Type>(MATRIX_SCHEMA, 0).assemble_into::<Vec<Vec<i32>>>(
+            &[1, 2, 3],
+            Some(&def_levels),
+            Some(&rep_levels),
+        )?;
+
+        assert_eq!(assembled, records);
+
+        Ok(())
+    }
+
+    #[test]
+    fn groups_on_the_path_are_left_out() -> Result<()> {
+        let schema = "
+            message schema {
+                OPTIONAL GROUP address {
+                    REQUIRED BYTE_ARRAY city (UTF8);
+                    OPTIONAL INT32 zip;
+                }
+                REPEATED GROUP people {
+                    REQUIRED INT32 age;
+                }
+            }
+        ";
+
+        // A null address and a null zip code both come out as a null.
+        let zips = assembler::<Int32Type>(schema, 1).assemble(&[5], Some(&[0, 1, 2]), None)?;
+        assert_eq!(zips, [Value::Null, Value::Null, Value::Leaf(5)]);
+
+        let ages = assembler::<Int32Type>(schema, 2).assemble_into::<Vec<i32>>(
+            &[30, 40],
+            Some(&[0, 1, 1]),
+            Some(&[0, 0, 1]),
+        )?;
+        assert_eq!(ages, [vec![], vec![30, 40]]);
+
+        let cities = assembler::<ByteArrayType>(schema, 0).assemble_into::<Option<String>>(
+            &["Oslo".into()],
+            Some(&[0, 1]),
+            None,
+        )?;
+        assert_eq!(cities, [None, Some("Oslo".to_owned())]);
+
+        Ok(())
+    }
+
+    const ADDRESS_SCHEMA: &str = "
+        message schema {
+            OPTIONAL GROUP address {
+                REQUIRED BYTE_ARRAY city (UTF8);
+                OPTIONAL INT32 zip;
+            }
+        }
+    ";
+
+    const PEOPLE_SCHEMA: &str = "
+        message schema {
+            REPEATED GROUP people {
+                REQUIRED INT32 age;
+                OPTIONAL BYTE_ARRAY name (UTF8);
+            }
+        }
+    ";
+
+    fn repeated_writer<T: DataType>(schema: &str, column: usize) -> RepeatedWriter<T> {
+        let schema = SchemaDescriptor::new(Arc::new(parse_schema(schema)));
+        RepeatedWriter::for_column(&schema, column)
+    }
+
+    #[test]
+    fn fields_of_an_optional_group_are_defined_below_it() -> Result<()> {
+        // A present address with a missing zip code defines the address but not the zip code.
+        let mut zips = repeated_writer::<Int32Type>(ADDRESS_SCHEMA, 1);
+        zips.push_value(Value::Null)?;
+        zips.push_value(Value::group([("zip", Value::Null)]))?;
+        zips.push_value(Value::group([("zip", Value::Leaf(5))]))?;
+        zips.push_value(Value::<i32>::group([]))?;
+
+        assert_eq!(zips.values(), [5]);
+        assert_eq!(zips.def_levels(), Some(&[0, 1, 2, 1][..]));
+        assert_eq!(zips.rep_levels(), None);
+
+        let mut cities = repeated_writer::<ByteArrayType>(ADDRESS_SCHEMA, 0);
+        cities.push_value(Value::Null)?;
+        cities.push_value(Value::group([("city", "Oslo".into_value())]))?;
+
+        assert_eq!(cities.values(), [ByteArray::from("Oslo")]);
+        assert_eq!(cities.def_levels(), Some(&[0, 1][..]));
+
+        // The city is required, so an address without one does not fit the column.
+        assert!(cities.push_value(Value::group([])).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn repeated_groups_repeat_every_field() -> Result<()> {
+        // Only the field on the path of the column is written, the others are left out.
+        let person = |age: i32| Value::group([("age", Value::Leaf(age)), ("name", Value::Null)]);
+
+        let mut ages = repeated_writer::<Int32Type>(PEOPLE_SCHEMA, 0);
+        ages.push_value(Vec::<Value<i32>>::new())?;
+        ages.push_value(vec![person(30), person(40)])?;
+        ages.push_value(vec![person(50)])?;
+
+        assert_eq!(ages.values(), [30, 40, 50]);
+        assert_eq!(ages.def_levels(), Some(&[0, 1, 1, 1][..]));
+        assert_eq!(ages.rep_levels(), Some(&[0, 0, 1, 0][..]));
+
+        let mut names = repeated_writer::<ByteArrayType>(PEOPLE_SCHEMA, 1);
+        names.push_value(Vec::<Value>::new())?;
+        names.push_value(vec![
+            Value::group([("age", Value::Null), ("name", "Ada".into_value())]),
+            Value::group([("age", Value::Null)]),
+        ])?;
+
+        assert_eq!(names.values(), [ByteArray::from("Ada")]);
+        assert_eq!(names.def_levels(), Some(&[0, 2, 1][..]));
+        assert_eq!(names.rep_levels(), Some(&[0, 0, 1][..]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn a_failed_push_leaves_the_writer_as_it_was() -> Result<()> {
+        let mut ages = repeated_writer::<Int32Type>(PEOPLE_SCHEMA, 0);
+        ages.push_value(vec![Value::group([("age", Value::Leaf(30))])])?;
+
+        // The first person is pushed before the second one, without an age, fails.
+        let error = ages
+            .push_value(vec![
+                Value::group([("age", Value::Leaf(40))]),
+                Value::group([("name", Value::Null)]),
+            ])
+            .unwrap_err();
+
+        assert!(error.to_string().contains("Expected a value"), "{error}");
+        assert_eq!(ages.values(), [30]);
+        assert_eq!(ages.def_levels(), Some(&[1][..]));
+        assert_eq!(ages.rep_levels(), Some(&[0][..]));
+
+        assert!(ages.push_value(Value::Leaf(50)).is_err());
+        assert_eq!(ages.values(), [30]);
+        assert_eq!(ages.def_levels(), Some(&[1][..]));
+        assert_eq!(ages.rep_levels(), Some(&[0][..]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn nullable_lists_tell_null_and_empty_lists_and_null_elements_apart() {
+        let mut names = repeated_writer::<ByteArrayType>(NULLABLE_NAMES_SCHEMA, 0);
+        names.push_null();
+        names.push(Vec::<&str>::new().into_iter());
+        names.push_with_null_elements([None::<&str>].into_iter());
+        names.push(["Ada", "Bo"].into_iter());
+
+        assert_eq!(
+            names.values(),
+            [ByteArray::from("Ada"), ByteArray::from("Bo")]
+        );
+        assert_eq!(names.def_levels(), Some(&[0, 1, 2, 3, 3][..]));
+        assert_eq!(names.rep_levels(), Some(&[0, 0, 0, 0, 1][..]));
+    }
+
+    #[test]
+    fn inconsistent_values_and_levels_are_rejected() {
+        let matrix = assembler::<Int32Type>(MATRIX_SCHEMA, 0);
+
+        let error = |result: Result<Vec<Value<i32>>>| result.unwrap_err().to_string();
+
+        assert!(error(matrix.assemble(&[1], Some(&[2, 2]), Some(&[0, 2])))
+            .contains("more defined levels than values"));
+        assert!(
+            error(matrix.assemble(&[1, 2], Some(&[2, 2]), Some(&[1, 0])))
+                .contains("Expected a record to start")
+        );
+        assert!(
+            error(matrix.assemble(&[1, 2], Some(&[2]), Some(&[0]))).contains("Only 1 of 2 values")
+        );
+        assert!(error(matrix.assemble(&[1], Some(&[2]), Some(&[0, 0])))
+            .contains("as many repetition levels"));
+        assert!(error(matrix.assemble(&[1], None, Some(&[0]))).contains("definition levels"));
+        assert!(error(matrix.assemble(&[1], Some(&[2]), None)).contains("repetition levels"));
+    }
+}
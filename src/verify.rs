@@ -0,0 +1,307 @@
+//! Comparing two Parquet files record by record.
+
+use std::fmt;
+
+use anyhow::Result;
+use parquet::{
+    column::reader::{get_typed_column_reader, ColumnReaderImpl},
+    data_type::{AsBytes, DataType},
+    file::{
+        reader::{ChunkReader, FileReader},
+        serialized_reader::SerializedFileReader,
+    },
+    schema::types::ColumnDescriptor,
+};
+
+use crate::{schema::check_compatible, with_data_type};
+
+/// Number of levels read from each file per `read_records` call.
+const BATCH_SIZE: usize = 1024;
+
+/// The first place where two files disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// Dotted path of the leaf column.
+    pub column: String,
+    /// Index of the record in the file, counting from zero.
+    pub record: usize,
+    /// What differs.
+    pub detail: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "column {} diverges at record {}: {}",
+            self.column, self.record, self.detail
+        )
+    }
+}
+
+/// Summary of a comparison, with the first divergence if there was one.
+#[derive(Debug, Default, Clone)]
+pub struct VerifyReport {
+    pub columns: usize,
+    pub records: usize,
+    pub levels: usize,
+    pub divergence: Option<Divergence>,
+}
+
+impl VerifyReport {
+    pub fn is_identical(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+/// Reads `expected` and `actual` column by column and compares their values, definition levels
+/// and repetition levels. Row group boundaries and column names are ignored, only the records
+/// matter.
+pub fn verify<E, A>(expected: E, actual: A) -> Result<VerifyReport>
+where
+    E: ChunkReader + 'static,
+    A: ChunkReader + 'static,
+{
+    let expected = SerializedFileReader::new(expected)?;
+    let actual = SerializedFileReader::new(actual)?;
+
+    let expected_schema = expected.metadata().file_metadata().schema_descr_ptr();
+    let actual_schema = actual.metadata().file_metadata().schema_descr_ptr();
+
+    check_compatible(&expected_schema, &actual_schema)?;
+
+    let mut report = VerifyReport::default();
+
+    for j in 0..expected_schema.num_columns() {
+        let descr = expected_schema.column(j);
+
+        let column = with_data_type!(descr.physical_type(), T => {
+            verify_column::<T>(&expected, &actual, j, &descr)?
+        });
+
+        report.columns += 1;
+        report.records = report.records.max(column.records);
+        report.levels += column.levels;
+
+        if column.divergence.is_some() {
+            report.divergence = column.divergence;
+            break;
+        }
+    }
+
+    Ok(report)
+}
+
+struct ColumnVerification {
+    records: usize,
+    levels: usize,
+    divergence: Option<Divergence>,
+}
+
+fn verify_column<T: DataType>(
+    expected: &dyn FileReader,
+    actual: &dyn FileReader,
+    column: usize,
+    descr: &ColumnDescriptor,
+) -> Result<ColumnVerification> {
+    let mut expected = ColumnStream::<T>::new(expected, column, descr);
+    let mut actual = ColumnStream::<T>::new(actual, column, descr);
+
+    let mut records = 0;
+    let mut levels = 0;
+
+    let divergence = |record: usize, detail: String| Divergence {
+        column: descr.path().string(),
+        record,
+        detail,
+    };
+
+    loop {
+        let (expected_level, actual_level) = match (expected.next()?, actual.next()?) {
+            (None, None) => break,
+            (Some(_), None) => {
+                return Ok(ColumnVerification {
+                    records,
+                    levels,
+                    divergence: Some(divergence(
+                        records,
+                        format!("rewritten column ends after {levels} levels"),
+                    )),
+                })
+            }
+            (None, Some(_)) => {
+                return Ok(ColumnVerification {
+                    records,
+                    levels,
+                    divergence: Some(divergence(
+                        records,
+                        format!("rewritten column has more than {levels} levels"),
+                    )),
+                })
+            }
+            (Some(expected_level), Some(actual_level)) => (expected_level, actual_level),
+        };
+
+        if expected_level.rep_level == 0 {
+            records += 1;
+        }
+
+        let record = records.saturating_sub(1);
+
+        let detail = if expected_level.def_level != actual_level.def_level {
+            Some(format!(
+                "definition level {} != {} at level {levels}",
+                expected_level.def_level, actual_level.def_level
+            ))
+        } else if expected_level.rep_level != actual_level.rep_level {
+            Some(format!(
+                "repetition level {} != {} at level {levels}",
+                expected_level.rep_level, actual_level.rep_level
+            ))
+        } else if !same_value(&expected_level.value, &actual_level.value) {
+            Some(format!(
+                "value {:?} != {:?} at level {levels}",
+                expected_level.value, actual_level.value
+            ))
+        } else {
+            None
+        };
+
+        levels += 1;
+
+        if let Some(detail) = detail {
+            return Ok(ColumnVerification {
+                records,
+                levels,
+                divergence: Some(divergence(record, detail)),
+            });
+        }
+    }
+
+    Ok(ColumnVerification {
+        records,
+        levels,
+        divergence: None,
+    })
+}
+
+/// Compares values by their bytes rather than with `PartialEq`, which is the bits of FLOAT and
+/// DOUBLE values: a NaN equals itself and 0.0 differs from -0.0.
+fn same_value<V: AsBytes>(expected: &Option<V>, actual: &Option<V>) -> bool {
+    match (expected, actual) {
+        (Some(expected), Some(actual)) => expected.as_bytes() == actual.as_bytes(),
+        (expected, actual) => expected.is_none() && actual.is_none(),
+    }
+}
+
+struct Level<T: DataType> {
+    def_level: i16,
+    rep_level: i16,
+    value: Option<T::T>,
+}
+
+/// Iterates the levels of one leaf column across all row groups of a file.
+struct ColumnStream<'a, T: DataType> {
+    reader: &'a dyn FileReader,
+    column: usize,
+    max_def_level: i16,
+    max_rep_level: i16,
+    next_row_group: usize,
+    column_reader: Option<ColumnReaderImpl<T>>,
+    values: Vec<T::T>,
+    def_levels: Vec<i16>,
+    rep_levels: Vec<i16>,
+    levels_read: usize,
+    level_pos: usize,
+    value_pos: usize,
+}
+
+impl<'a, T: DataType> ColumnStream<'a, T> {
+    fn new(reader: &'a dyn FileReader, column: usize, descr: &ColumnDescriptor) -> Self {
+        ColumnStream {
+            reader,
+            column,
+            max_def_level: descr.max_def_level(),
+            max_rep_level: descr.max_rep_level(),
+            next_row_group: 0,
+            column_reader: None,
+            values: vec![T::T::default(); BATCH_SIZE],
+            def_levels: vec![0; BATCH_SIZE],
+            rep_levels: vec![0; BATCH_SIZE],
+            levels_read: 0,
+            level_pos: 0,
+            value_pos: 0,
+        }
+    }
+
+    fn next(&mut self) -> Result<Option<Level<T>>> {
+        while self.level_pos == self.levels_read {
+            if !self.fill()? {
+                return Ok(None);
+            }
+        }
+
+        let def_level = if self.max_def_level > 0 {
+            self.def_levels[self.level_pos]
+        } else {
+            0
+        };
+        let rep_level = if self.max_rep_level > 0 {
+            self.rep_levels[self.level_pos]
+        } else {
+            0
+        };
+
+        let value = if def_level == self.max_def_level {
+            self.value_pos += 1;
+            Some(self.values[self.value_pos - 1].clone())
+        } else {
+            None
+        };
+
+        self.level_pos += 1;
+
+        Ok(Some(Level {
+            def_level,
+            rep_level,
+            value,
+        }))
+    }
+
+    /// Reads the next batch of levels, moving on to the next row group when the current one is
+    /// exhausted. Returns `false` at the end of the file.
+    fn fill(&mut self) -> Result<bool> {
+        loop {
+            let Some(column_reader) = self.column_reader.as_mut() else {
+                if self.next_row_group == self.reader.num_row_groups() {
+                    return Ok(false);
+                }
+
+                let row_group_reader = self.reader.get_row_group(self.next_row_group)?;
+                self.column_reader = Some(get_typed_column_reader(
+                    row_group_reader.get_column_reader(self.column)?,
+                ));
+                self.next_row_group += 1;
+                continue;
+            };
+
+            let (_, _, levels_read) = column_reader.read_records(
+                BATCH_SIZE,
+                Some(&mut self.def_levels[..]),
+                Some(&mut self.rep_levels[..]),
+                &mut self.values[..],
+            )?;
+
+            if levels_read == 0 {
+                self.column_reader = None;
+                continue;
+            }
+
+            self.levels_read = levels_read;
+            self.level_pos = 0;
+            self.value_pos = 0;
+
+            return Ok(true);
+        }
+    }
+}
@@ -0,0 +1,14 @@
+//! Fixtures shared by the integration tests.
+
+use std::sync::Arc;
+
+use parquet::{basic::Compression, file::properties::WriterProperties};
+
+/// The writer properties test files are written with.
+pub fn props() -> Arc<WriterProperties> {
+    Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build(),
+    )
+}
@@ -0,0 +1,840 @@
+mod common;
+
+use std::{io::Write, sync::Arc};
+
+use anyhow::Result;
+use bytes::Bytes;
+use common::props;
+use parquet::{
+    arrow::arrow_reader::ParquetRecordBatchReaderBuilder,
+    basic::{Compression, ZstdLevel},
+    data_type::{BoolType, ByteArrayType, DoubleType, FloatType, Int64Type},
+    file::{
+        metadata::KeyValue,
+        properties::{EnabledStatistics, WriterProperties, WriterVersion},
+        reader::FileReader,
+        serialized_reader::{ReadOptionsBuilder, SerializedFileReader},
+    },
+    schema::types::SchemaDescriptor,
+};
+use parquet_bug::{
+    chunk_matches_properties, concat, output_schema, parse_schema, preserved_properties, rewrite,
+    testdata, testdata::RepeatedWriter, verify, with_compression_overrides, CompressionOverride,
+    FileChunkReader, RewriteMode, RewriteOptions, RowGroupSize,
+};
+
+/// Writes a file of [`testdata::NAMES_SCHEMA`] with a list of `len` names per entry of `lens`.
+fn names_file(lens: &[usize]) -> Result<Bytes> {
+    names_row_groups(&[lens])
+}
+
+/// Writes a file of [`testdata::NAMES_SCHEMA`] with a row group per entry of `row_groups`, holding
+/// a list of `len` names per `len` in the entry.
+fn names_row_groups(row_groups: &[&[usize]]) -> Result<Bytes> {
+    let schema = Arc::new(parse_schema(testdata::NAMES_SCHEMA));
+
+    testdata::create_records_file(schema, props(), row_groups, |_, lens| {
+        let mut repeated_writer = RepeatedWriter::new();
+        for len in *lens {
+            repeated_writer.push(testdata::names(*len).into_iter());
+        }
+
+        Ok(vec![Box::new(repeated_writer)])
+    })
+}
+
+/// Number of rows in each row group of `bytes`.
+fn row_group_sizes(bytes: Bytes) -> Result<Vec<i64>> {
+    let reader = SerializedFileReader::new(bytes)?;
+
+    Ok(reader
+        .metadata()
+        .row_groups()
+        .iter()
+        .map(|row_group| row_group.num_rows())
+        .collect())
+}
+
+#[test]
+fn records_longer_than_the_level_buffer_are_written_whole() -> Result<()> {
+    let bytes = names_file(&[12, 3, 0, 7, 5])?;
+
+    let mut options = RewriteOptions::new(props());
+    options.batch_size = 5;
+    options.level_buffer_capacity = 5;
+    options.raw_copy = false;
+
+    let mut output = Vec::new();
+    let report = rewrite(bytes.clone(), &mut output, &options)?;
+
+    assert_eq!(report.rows, 5);
+    assert_eq!(report.values_written, 27);
+
+    let verification = verify(bytes, Bytes::from(output))?;
+
+    assert_eq!(verification.divergence, None);
+    assert_eq!(verification.records, 5);
+
+    Ok(())
+}
+
+#[test]
+fn low_level_and_arrow_rewrites_agree_on_nested_lists() -> Result<()> {
+    let bytes = names_file(&[12, 3, 0, 7, 5])?;
+
+    let mut options = RewriteOptions::new(props());
+    options.batch_size = 2;
+    options.raw_copy = false;
+
+    let mut low_level = Vec::new();
+    rewrite(bytes.clone(), &mut low_level, &options)?;
+
+    options.mode = RewriteMode::Arrow;
+
+    let mut arrow = Vec::new();
+    let report = rewrite(bytes.clone(), &mut arrow, &options)?;
+
+    assert_eq!(report.rows, 5);
+
+    let low_level = Bytes::from(low_level);
+    let arrow = Bytes::from(arrow);
+
+    let verification = verify(low_level.clone(), arrow.clone())?;
+
+    assert_eq!(verification.divergence, None);
+    assert_eq!(verification.records, 5);
+    assert_eq!(verify(bytes, arrow.clone())?.divergence, None);
+
+    let read_batches = |bytes: Bytes| -> Result<Vec<_>> {
+        Ok(ParquetRecordBatchReaderBuilder::try_new(bytes)?
+            .build()?
+            .collect::<Result<Vec<_>, _>>()?)
+    };
+
+    assert_eq!(read_batches(low_level)?, read_batches(arrow)?);
+
+    Ok(())
+}
+
+#[test]
+fn arrow_rewrites_reject_row_groups_the_writer_would_split() -> Result<()> {
+    let bytes = names_file(&[12, 3, 0, 7, 5])?;
+
+    let mut options = RewriteOptions::new(Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_max_row_group_size(4)
+            .build(),
+    ));
+    options.mode = RewriteMode::Arrow;
+
+    let error = rewrite(bytes.clone(), Vec::new(), &options).unwrap_err();
+    assert!(
+        error.to_string().contains("limit row groups to 4 rows"),
+        "{error}"
+    );
+
+    options.row_group_size = Some(RowGroupSize::Rows(4));
+
+    let mut output = Vec::new();
+    rewrite(bytes, &mut output, &options)?;
+    assert_eq!(row_group_sizes(Bytes::from(output))?, [4, 1]);
+
+    Ok(())
+}
+
+#[test]
+fn row_groups_dropped_by_the_filter_are_skipped_before_a_raw_copy() -> Result<()> {
+    let short = names_file(&[1, 3, 0, 2])?;
+    let long = names_file(&[5, 4, 7])?;
+
+    // The properties match the inputs, so the row groups kept whole are copied as they are.
+    let mut options = RewriteOptions::new(props());
+    options.filter = Some("len(names) > 3".parse()?);
+
+    let mut output = Vec::new();
+    let report = concat(vec![short.clone(), long.clone()], &mut output, &options)?;
+
+    assert_eq!(report.rows, 3);
+    assert_eq!(report.rows_dropped, 4);
+    assert_eq!(report.row_groups, 1);
+    assert_eq!(report.chunks_copied, 1);
+    assert_eq!(verify(long.clone(), Bytes::from(output))?.divergence, None);
+
+    options.filter = Some("len(names) < 4".parse()?);
+
+    let mut output = Vec::new();
+    let report = concat(
+        vec![long.clone(), short.clone(), long],
+        &mut output,
+        &options,
+    )?;
+
+    assert_eq!(report.rows, 4);
+    assert_eq!(report.rows_dropped, 6);
+    assert_eq!(report.row_groups, 1);
+    assert_eq!(report.chunks_copied, 1);
+    assert_eq!(verify(short, Bytes::from(output))?.divergence, None);
+
+    Ok(())
+}
+
+/// Writes a file with an id, a list of names and an optional city for three people.
+fn people_file(props: Arc<WriterProperties>) -> Result<Bytes> {
+    let schema = Arc::new(parse_schema(
+        "
+        message schema {
+            REQUIRED INT64 id;
+            REQUIRED GROUP names (LIST) {
+                REPEATED GROUP list {
+                    REQUIRED BYTE_ARRAY list_element (UTF8);
+                }
+            }
+            OPTIONAL BYTE_ARRAY city (UTF8);
+        }
+        ",
+    ));
+    let descr = SchemaDescriptor::new(schema.clone());
+
+    let mut ids = RepeatedWriter::<Int64Type>::for_column(&descr, 0);
+    let mut names = RepeatedWriter::<ByteArrayType>::for_column(&descr, 1);
+    let mut cities = RepeatedWriter::<ByteArrayType>::for_column(&descr, 2);
+
+    for (id, len, city) in [(1, 2, Some("Oslo")), (2, 0, None), (3, 5, Some("Lima"))] {
+        ids.push_value(id)?;
+        names.push(testdata::names(len).into_iter());
+        cities.push_value(city)?;
+    }
+
+    testdata::create_row_groups_file(schema, props, &[vec![&ids, &names, &cities]])
+}
+
+#[test]
+fn columns_are_dropped_added_and_reordered_by_the_output_schema() -> Result<()> {
+    let bytes = people_file(props())?;
+
+    let mut options = RewriteOptions::new(props());
+    options.schema = Some(Arc::new(parse_schema(
+        "
+        message schema {
+            OPTIONAL BYTE_ARRAY city (UTF8);
+            REQUIRED GROUP names (LIST) {
+                REPEATED GROUP list {
+                    REQUIRED BYTE_ARRAY list_element (UTF8);
+                }
+            }
+            OPTIONAL INT32 extra;
+        }
+        ",
+    )));
+
+    let mut output = Vec::new();
+    let report = rewrite(bytes.clone(), &mut output, &options)?;
+
+    assert_eq!(report.rows, 3);
+    assert_eq!(report.columns, 3);
+
+    let read_batch = |bytes: Bytes| -> Result<_> {
+        let mut batches = ParquetRecordBatchReaderBuilder::try_new(bytes)?.build()?;
+        Ok(batches.next().expect("a batch")?)
+    };
+
+    let input = read_batch(bytes)?;
+    let output = read_batch(Bytes::from(output))?;
+
+    let names: Vec<_> = output
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| f.name().clone())
+        .collect();
+    assert_eq!(names, ["city", "names", "extra"]);
+
+    assert_eq!(output.column(0), input.column(2));
+    assert_eq!(output.column(1), input.column(1));
+    assert_eq!(output.column(2).null_count(), 3);
+
+    Ok(())
+}
+
+#[test]
+fn only_projected_columns_are_written() -> Result<()> {
+    let bytes = people_file(props())?;
+
+    let mut options = RewriteOptions::new(props());
+    options.projection = vec!["city".parse()?, "names.list.list_element".parse()?];
+
+    let mut output = Vec::new();
+    let report = rewrite(bytes.clone(), &mut output, &options)?;
+
+    assert_eq!(report.rows, 3);
+    assert_eq!(report.columns, 2);
+
+    let read_batch = |bytes: Bytes| -> Result<_> {
+        let mut batches = ParquetRecordBatchReaderBuilder::try_new(bytes)?.build()?;
+        Ok(batches.next().expect("a batch")?)
+    };
+
+    let input = read_batch(bytes)?;
+    let output = read_batch(Bytes::from(output))?;
+
+    let names: Vec<_> = output
+        .schema()
+        .fields()
+        .iter()
+        .map(|f| f.name().clone())
+        .collect();
+    assert_eq!(names, ["names", "city"]);
+
+    assert_eq!(output.column(0), input.column(1));
+    assert_eq!(output.column(1), input.column(2));
+
+    Ok(())
+}
+
+#[test]
+fn only_records_matching_the_filter_are_written() -> Result<()> {
+    let bytes = names_file(&[5, 1, 4, 0, 2, 3])?;
+
+    let mut options = RewriteOptions::new(props());
+    options.filter = Some("len(names) > 1 and not len(names) = 4".parse()?);
+
+    for raw_copy in [true, false] {
+        options.raw_copy = raw_copy;
+
+        let mut output = Vec::new();
+        let report = rewrite(bytes.clone(), &mut output, &options)?;
+
+        assert_eq!(report.rows, 3);
+        assert_eq!(report.rows_dropped, 3);
+        assert_eq!(report.chunks_copied, 0);
+
+        let verification = verify(names_file(&[5, 2, 3])?, Bytes::from(output))?;
+
+        assert_eq!(verification.divergence, None);
+        assert_eq!(verification.records, 3);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn a_batch_size_of_zero_is_rejected() -> Result<()> {
+    let mut options = RewriteOptions::new(props());
+    options.batch_size = 0;
+
+    assert!(rewrite(names_file(&[1])?, Vec::new(), &options).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn zero_threads_are_rejected() -> Result<()> {
+    let mut options = RewriteOptions::new(props());
+    options.threads = 0;
+
+    assert!(rewrite(names_file(&[1])?, Vec::new(), &options).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn a_row_group_size_of_zero_is_rejected() -> Result<()> {
+    let mut options = RewriteOptions::new(props());
+
+    for size in [RowGroupSize::Rows(0), RowGroupSize::Bytes(0)] {
+        options.row_group_size = Some(size);
+
+        for mode in [RewriteMode::LowLevel, RewriteMode::Arrow] {
+            options.mode = mode;
+
+            assert!(rewrite(names_file(&[1])?, Vec::new(), &options).is_err());
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn float_values_are_verified_by_their_bits() -> Result<()> {
+    let float_file = |values: &[f32]| -> Result<Bytes> {
+        let schema = Arc::new(parse_schema("message schema { REQUIRED FLOAT score; }"));
+        let mut scores =
+            RepeatedWriter::<FloatType>::for_column(&SchemaDescriptor::new(schema.clone()), 0);
+
+        for value in values {
+            scores.push_value(*value)?;
+        }
+
+        testdata::create_row_groups_file(schema, props(), &[vec![&scores]])
+    };
+
+    let bytes = float_file(&[1.5, f32::NAN, -0.0])?;
+
+    assert_eq!(verify(bytes.clone(), bytes.clone())?.divergence, None);
+
+    let divergence = verify(bytes, float_file(&[1.5, f32::NAN, 0.0])?)?
+        .divergence
+        .expect("-0.0 and 0.0 differ");
+
+    assert_eq!(divergence.record, 2);
+
+    Ok(())
+}
+
+#[test]
+fn chunks_matching_the_writer_properties_are_copied_as_they_are() -> Result<()> {
+    let bytes = names_row_groups(&[&[5, 1], &[0, 3]])?;
+
+    let mut output = Vec::new();
+    let report = rewrite(bytes.clone(), &mut output, &RewriteOptions::new(props()))?;
+
+    assert_eq!(report.row_groups, 2);
+    assert_eq!(report.chunks_copied, 2);
+    assert_eq!(report.values_written, 0);
+    assert_eq!(verify(bytes, Bytes::from(output))?.divergence, None);
+
+    Ok(())
+}
+
+#[test]
+fn preserved_properties_write_chunks_like_the_input() -> Result<()> {
+    let bytes = people_file(Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::try_new(3)?))
+            .set_dictionary_enabled(false)
+            .set_statistics_enabled(EnabledStatistics::Chunk)
+            .set_created_by("people writer 1.0".to_owned())
+            .set_key_value_metadata(Some(vec![KeyValue::new(
+                "origin".to_owned(),
+                "census".to_owned(),
+            )]))
+            .build(),
+    ))?;
+
+    let input = SerializedFileReader::new(bytes.clone())?;
+    let properties = preserved_properties(input.metadata()).build();
+
+    let mut output = Vec::new();
+    let report = rewrite(
+        bytes,
+        &mut output,
+        &RewriteOptions::new(Arc::new(properties)),
+    )?;
+
+    assert_eq!(report.chunks_copied, 3);
+    assert_eq!(report.values_written, 0);
+
+    let output = SerializedFileReader::new(Bytes::from(output))?;
+    let (input, output) = (input.metadata(), output.metadata());
+
+    assert_eq!(
+        output.file_metadata().created_by(),
+        Some("people writer 1.0")
+    );
+    assert_eq!(
+        output.file_metadata().key_value_metadata(),
+        input.file_metadata().key_value_metadata()
+    );
+
+    for (expected, actual) in input
+        .row_group(0)
+        .columns()
+        .iter()
+        .zip(output.row_group(0).columns())
+    {
+        assert!(matches!(expected.compression(), Compression::ZSTD(_)));
+        assert_eq!(actual.compression(), expected.compression());
+        assert_eq!(actual.encodings(), expected.encodings());
+        assert_eq!(actual.dictionary_page_offset(), None);
+        assert_eq!(actual.statistics(), expected.statistics());
+        assert_eq!(actual.column_index_offset(), None);
+
+        // The defaults would have re-encoded every chunk.
+        let version = input.file_metadata().version();
+        assert!(!chunk_matches_properties(expected, version, &props()));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn copied_chunks_keep_their_page_index() -> Result<()> {
+    let bytes = people_file(props())?;
+
+    let mut output = Vec::new();
+    let report = rewrite(bytes.clone(), &mut output, &RewriteOptions::new(props()))?;
+    let output = Bytes::from(output);
+
+    assert_eq!(report.chunks_copied, 3);
+    assert_eq!(verify(bytes.clone(), output.clone())?.divergence, None);
+
+    let read_options = || ReadOptionsBuilder::new().with_page_index().build();
+    let input = SerializedFileReader::new_with_options(bytes.clone(), read_options())?;
+    let output = SerializedFileReader::new_with_options(output, read_options())?;
+    let (input, output) = (input.metadata(), output.metadata());
+
+    assert!(input.column_index().is_some());
+    assert_eq!(output.column_index(), input.column_index());
+
+    let (Some(expected), Some(actual)) = (input.offset_index(), output.offset_index()) else {
+        panic!("both files have an offset index");
+    };
+
+    for (j, column) in output.row_group(0).columns().iter().enumerate() {
+        let (expected, actual) = (&expected[0][j], &actual[0][j]);
+
+        assert_eq!(actual.len(), expected.len());
+        assert_eq!(actual[0].offset, column.data_page_offset());
+        assert!(actual.iter().zip(expected).all(|(actual, expected)| {
+            actual.compressed_page_size == expected.compressed_page_size
+                && actual.first_row_index == expected.first_row_index
+        }));
+    }
+
+    // Chunks written with another data page version or statistics level are re-encoded.
+    for properties in [
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_writer_version(WriterVersion::PARQUET_2_0),
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .set_statistics_enabled(EnabledStatistics::Chunk),
+    ] {
+        let options = RewriteOptions::new(Arc::new(properties.build()));
+        let report = rewrite(bytes.clone(), Vec::new(), &options)?;
+
+        assert_eq!(report.chunks_copied, 0);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn a_compression_override_re_encodes_the_column() -> Result<()> {
+    let bytes = people_file(props())?;
+    let input = SerializedFileReader::new(bytes.clone())?;
+    let schema = input.metadata().file_metadata().schema_descr();
+
+    let properties = with_compression_overrides(
+        WriterProperties::builder().set_compression(Compression::SNAPPY),
+        &["names.list.list_element=UNCOMPRESSED".parse()?],
+        schema,
+    )?;
+
+    let mut output = Vec::new();
+    let report = rewrite(
+        bytes.clone(),
+        &mut output,
+        &RewriteOptions::new(Arc::new(properties.build())),
+    )?;
+    let output = Bytes::from(output);
+
+    assert_eq!(report.chunks_copied, 2);
+    assert_eq!(report.values_written, 7);
+    assert_eq!(verify(bytes, output.clone())?.divergence, None);
+
+    let output = SerializedFileReader::new(output)?;
+    let codecs: Vec<_> = output
+        .metadata()
+        .row_group(0)
+        .columns()
+        .iter()
+        .map(|column| (column.column_path().string(), column.compression()))
+        .collect();
+
+    assert_eq!(
+        codecs,
+        [
+            ("id".to_owned(), Compression::SNAPPY),
+            (
+                "names.list.list_element".to_owned(),
+                Compression::UNCOMPRESSED
+            ),
+            ("city".to_owned(), Compression::SNAPPY),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn files_on_disk_are_copied_chunk_by_chunk() -> Result<()> {
+    let mut input = tempfile::NamedTempFile::new()?;
+    input.write_all(&names_row_groups(&[&[12, 3], &[0, 7, 5]])?)?;
+    input.flush()?;
+
+    let output = tempfile::NamedTempFile::new()?;
+    let report = rewrite(
+        FileChunkReader::open(input.path())?,
+        output.reopen()?,
+        &RewriteOptions::new(props()),
+    )?;
+
+    assert_eq!(report.row_groups, 2);
+    assert_eq!(report.chunks_copied, 2);
+
+    let verification = verify(
+        FileChunkReader::open(input.path())?,
+        FileChunkReader::open(output.path())?,
+    )?;
+
+    assert_eq!(verification.divergence, None);
+    assert_eq!(verification.records, 5);
+
+    Ok(())
+}
+
+#[test]
+fn compression_overrides_name_columns_of_the_output_schema() -> Result<()> {
+    let bytes = people_file(props())?;
+    let input = SerializedFileReader::new(bytes)?;
+    let input_schema = input.metadata().file_metadata().schema_descr();
+    let builder = || WriterProperties::builder().set_compression(Compression::SNAPPY);
+
+    let projected = SchemaDescriptor::new(output_schema(
+        input_schema,
+        None,
+        &["id".parse()?, "city".parse()?],
+    )?);
+
+    assert!(with_compression_overrides(builder(), &["city=ZSTD(3)".parse()?], &projected).is_ok());
+    assert!(with_compression_overrides(
+        builder(),
+        &["names.list.list_element=UNCOMPRESSED".parse()?],
+        &projected
+    )
+    .is_err());
+
+    let renamed = SchemaDescriptor::new(output_schema(
+        input_schema,
+        Some(&Arc::new(parse_schema(
+            "message schema { REQUIRED INT64 id; OPTIONAL BYTE_ARRAY town (UTF8); }",
+        ))),
+        &[],
+    )?);
+
+    assert!(with_compression_overrides(builder(), &["town=GZIP(6)".parse()?], &renamed).is_ok());
+    assert!(with_compression_overrides(builder(), &["city=GZIP(6)".parse()?], &renamed).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn malformed_compression_overrides_are_rejected() -> Result<()> {
+    assert!("names.list.list_element"
+        .parse::<CompressionOverride>()
+        .is_err());
+    assert!("names.list.list_element=LZ5"
+        .parse::<CompressionOverride>()
+        .is_err());
+    assert!("names.list.list_element=ZSTD(99)"
+        .parse::<CompressionOverride>()
+        .is_err());
+
+    let schema = SchemaDescriptor::new(Arc::new(parse_schema(testdata::NAMES_SCHEMA)));
+    let result = with_compression_overrides(
+        WriterProperties::builder(),
+        &["names.list=ZSTD(3)".parse()?],
+        &schema,
+    );
+
+    assert!(result.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn copied_chunks_mix_with_filtered_row_groups_and_re_encoded_columns() -> Result<()> {
+    let bytes = names_row_groups(&[&[5, 4], &[1, 6], &[7], &[2, 8]])?;
+
+    // The second and last row groups lose records, the others are copied as they are.
+    let mut options = RewriteOptions::new(props());
+    options.filter = Some("len(names) > 3".parse()?);
+
+    let mut output = Vec::new();
+    let report = rewrite(bytes.clone(), &mut output, &options)?;
+
+    assert_eq!(report.row_groups, 4);
+    assert_eq!(report.rows, 5);
+    assert_eq!(report.chunks_copied, 2);
+    assert_eq!(report.values_written, 14);
+    assert_eq!(
+        verify(names_file(&[5, 4, 6, 7, 8])?, Bytes::from(output))?.divergence,
+        None
+    );
+
+    // With the names re-encoded, the ids are still copied next to them.
+    let schema = Arc::new(parse_schema(
+        "
+        message schema {
+            REQUIRED INT64 id;
+            REQUIRED GROUP names (LIST) {
+                REPEATED GROUP list {
+                    REQUIRED BYTE_ARRAY list_element (UTF8);
+                }
+            }
+        }
+        ",
+    ));
+    let descr = SchemaDescriptor::new(schema.clone());
+
+    let bytes =
+        testdata::create_records_file(schema, props(), &[[5, 4], [1, 6]], |descr, lens| {
+            let mut ids = RepeatedWriter::<Int64Type>::for_column(descr, 0);
+            let mut names = RepeatedWriter::<ByteArrayType>::for_column(descr, 1);
+
+            for len in lens {
+                ids.push_value(*len as i64)?;
+                names.push(testdata::names(*len).into_iter());
+            }
+
+            Ok(vec![Box::new(ids), Box::new(names)])
+        })?;
+
+    let properties = with_compression_overrides(
+        WriterProperties::builder().set_compression(Compression::SNAPPY),
+        &["names.list.list_element=UNCOMPRESSED".parse()?],
+        &descr,
+    )?;
+
+    let mut output = Vec::new();
+    let report = rewrite(
+        bytes.clone(),
+        &mut output,
+        &RewriteOptions::new(Arc::new(properties.build())),
+    )?;
+
+    assert_eq!(report.row_groups, 2);
+    assert_eq!(report.chunks_copied, 2);
+    assert_eq!(report.values_written, 16);
+    assert_eq!(verify(bytes, Bytes::from(output))?.divergence, None);
+
+    Ok(())
+}
+
+#[test]
+fn row_groups_are_merged_and_split_across_inputs() -> Result<()> {
+    let first = names_row_groups(&[&[1, 2, 3], &[4, 5], &[6, 7, 8, 9, 10]])?;
+    let second = names_row_groups(&[&[2, 0]])?;
+    let all = names_file(&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 2, 0])?;
+
+    let mut options = RewriteOptions::new(props());
+    options.row_group_size = Some(RowGroupSize::Rows(5));
+    options.batch_size = 2;
+
+    let mut output = Vec::new();
+    let report = concat(vec![first.clone(), second.clone()], &mut output, &options)?;
+    let output = Bytes::from(output);
+
+    assert_eq!(report.row_groups, 3);
+    assert_eq!(report.rows, 12);
+    assert_eq!(row_group_sizes(output.clone())?, [5, 5, 2]);
+    assert_eq!(verify(all, output)?.divergence, None);
+
+    // Dropped records leave holes in the input row groups that the output row groups close.
+    options.row_group_size = Some(RowGroupSize::Rows(3));
+    options.filter = Some("len(names) > 2 and len(names) <> 6".parse()?);
+
+    let mut output = Vec::new();
+    let report = concat(vec![first, second], &mut output, &options)?;
+    let output = Bytes::from(output);
+
+    assert_eq!(report.rows, 7);
+    assert_eq!(report.rows_dropped, 5);
+    assert_eq!(row_group_sizes(output.clone())?, [3, 3, 1]);
+    assert_eq!(
+        verify(names_file(&[3, 4, 5, 7, 8, 9, 10])?, output)?.divergence,
+        None
+    );
+
+    Ok(())
+}
+
+#[test]
+fn columns_encoded_on_several_threads_match_a_sequential_rewrite() -> Result<()> {
+    let schema = Arc::new(parse_schema(
+        "
+        message schema {
+            REQUIRED INT64 id;
+            OPTIONAL DOUBLE score;
+            OPTIONAL BOOLEAN flag;
+            REQUIRED GROUP names (LIST) {
+                REPEATED GROUP list {
+                    REQUIRED BYTE_ARRAY list_element (UTF8);
+                }
+            }
+        }
+        ",
+    ));
+    let descr = SchemaDescriptor::new(schema.clone());
+
+    let bytes = testdata::create_records_file(schema, props(), &[0..3, 3..6], |descr, ids| {
+        let mut id_column = RepeatedWriter::<Int64Type>::for_column(descr, 0);
+        let mut scores = RepeatedWriter::<DoubleType>::for_column(descr, 1);
+        let mut flags = RepeatedWriter::<BoolType>::for_column(descr, 2);
+        let mut names = RepeatedWriter::<ByteArrayType>::for_column(descr, 3);
+
+        for id in ids.clone() {
+            id_column.push_value(id)?;
+            scores.push_value((id % 3 != 1).then_some(id as f64 / 2.0))?;
+            flags.push_value((id % 2 == 0).then_some(id % 4 == 0))?;
+            names.push(testdata::names(id as usize).into_iter());
+        }
+
+        Ok(vec![
+            Box::new(id_column),
+            Box::new(scores),
+            Box::new(flags),
+            Box::new(names),
+        ])
+    })?;
+
+    // The names are re-encoded and the new column is filled with nulls. The ids and scores of
+    // the first row group are copied as they are, but not the flags, which are written without a
+    // dictionary. The second row group loses a record to the filter.
+    let properties = with_compression_overrides(
+        WriterProperties::builder().set_compression(Compression::SNAPPY),
+        &["names.list.list_element=UNCOMPRESSED".parse()?],
+        &descr,
+    )?;
+
+    let mut options = RewriteOptions::new(Arc::new(properties.build()));
+    options.filter = Some("id <> 4".parse()?);
+    options.schema = Some(Arc::new(parse_schema(
+        "
+        message schema {
+            REQUIRED INT64 id;
+            OPTIONAL DOUBLE score;
+            OPTIONAL BOOLEAN flag;
+            REQUIRED GROUP names (LIST) {
+                REPEATED GROUP list {
+                    REQUIRED BYTE_ARRAY list_element (UTF8);
+                }
+            }
+            OPTIONAL INT32 extra;
+        }
+        ",
+    )));
+
+    let mut sequential = Vec::new();
+    rewrite(bytes.clone(), &mut sequential, &options)?;
+
+    options.threads = 4;
+
+    let mut threaded = Vec::new();
+    let report = rewrite(bytes, &mut threaded, &options)?;
+
+    assert_eq!(report.row_groups, 2);
+    assert_eq!(report.columns, 5);
+    assert_eq!(report.rows, 5);
+    assert_eq!(report.chunks_copied, 2);
+
+    let verification = verify(Bytes::from(sequential), Bytes::from(threaded))?;
+
+    assert_eq!(verification.divergence, None);
+    assert_eq!(verification.columns, 5);
+    assert_eq!(verification.records, 5);
+
+    Ok(())
+}
@@ -0,0 +1,157 @@
+mod common;
+
+use std::{fmt::Debug, sync::Arc};
+
+use anyhow::Result;
+use bytes::Bytes;
+use common::props;
+use parquet::{
+    column::reader::get_typed_column_reader,
+    data_type::{ByteArray, ByteArrayType},
+    file::{reader::FileReader, serialized_reader::SerializedFileReader},
+    schema::types::SchemaDescriptor,
+};
+use parquet_bug::{
+    parse_schema, rewrite, testdata,
+    testdata::{FromValue, IntoValue, RecordAssembler, RepeatedWriter},
+    RewriteOptions,
+};
+use proptest::{collection::vec, prelude::*};
+
+/// Lists of all sizes: mostly small ones, some empty and now and then one much longer than any
+/// read buffer.
+fn list() -> impl Strategy<Value = Vec<String>> {
+    prop_oneof![
+        6 => vec("[a-z ]{0,8}", 1..4),
+        2 => Just(Vec::new()),
+        1 => vec("[a-z ]{0,8}", 50..300),
+    ]
+}
+
+fn nullable_list() -> impl Strategy<Value = Option<Vec<Option<String>>>> {
+    prop_oneof![
+        1 => Just(None),
+        4 => list().prop_flat_map(|list| {
+            let len = list.len();
+            (Just(list), vec(any::<bool>(), len)).prop_map(|(list, nulls)| {
+                let elements = list.into_iter().zip(nulls);
+                Some(elements.map(|(name, null)| (!null).then_some(name)).collect())
+            })
+        }),
+    ]
+}
+
+/// Writes `records` as the only column of `schema`.
+fn write<R: IntoValue<ByteArray> + Clone>(schema: &str, records: &[R]) -> Result<Bytes> {
+    let schema = Arc::new(parse_schema(schema));
+    let mut repeated_writer =
+        RepeatedWriter::<ByteArrayType>::for_column(&SchemaDescriptor::new(schema.clone()), 0);
+
+    for record in records {
+        repeated_writer.push_value(record.clone())?;
+    }
+
+    testdata::create_parquet_file(schema, props(), &repeated_writer)
+}
+
+/// Reads the only column of `bytes` with `read_records` into buffers of `capacity` levels,
+/// asking for `batch_size` records at a time, and reassembles the records from what was read.
+fn read<R: FromValue<ByteArray>>(
+    bytes: Bytes,
+    batch_size: usize,
+    capacity: usize,
+) -> Result<Vec<R>> {
+    let reader = SerializedFileReader::new(bytes)?;
+    let schema = reader.metadata().file_metadata().schema_descr_ptr();
+
+    let mut values = Vec::new();
+    let mut def_levels = Vec::new();
+    let mut rep_levels = Vec::new();
+
+    let mut value_buffer = vec![ByteArray::default(); capacity];
+    let mut def_buffer = vec![0; capacity];
+    let mut rep_buffer = vec![0; capacity];
+
+    for i in 0..reader.num_row_groups() {
+        let mut column_reader = get_typed_column_reader::<ByteArrayType>(
+            reader.get_row_group(i)?.get_column_reader(0)?,
+        );
+
+        loop {
+            let (records_read, values_read, levels_read) = column_reader.read_records(
+                batch_size,
+                Some(&mut def_buffer[..]),
+                Some(&mut rep_buffer[..]),
+                &mut value_buffer[..],
+            )?;
+
+            // A record left unfinished by the previous call may be counted without reading any
+            // levels, so only a call that reads nothing at all ends the chunk.
+            if records_read == 0 && levels_read == 0 {
+                break;
+            }
+
+            values.extend_from_slice(&value_buffer[..values_read]);
+            def_levels.extend_from_slice(&def_buffer[..levels_read]);
+            rep_levels.extend_from_slice(&rep_buffer[..levels_read]);
+        }
+    }
+
+    RecordAssembler::<ByteArrayType>::for_column(&schema, 0).assemble_into(
+        &values,
+        Some(&def_levels),
+        Some(&rep_levels),
+    )
+}
+
+/// Rewrites `bytes` reading `batch_size` records at a time into buffers of `capacity` levels.
+fn rewrite_with(bytes: Bytes, batch_size: usize, capacity: usize) -> Result<Bytes> {
+    let mut options = RewriteOptions::new(props());
+    options.batch_size = batch_size;
+    options.level_buffer_capacity = capacity;
+    options.raw_copy = false;
+
+    let mut output = Vec::new();
+    rewrite(bytes, &mut output, &options)?;
+
+    Ok(Bytes::from(output))
+}
+
+/// Writes `records`, then checks that reading them back gives the same records, both from the
+/// written file and from a rewrite of it.
+fn round_trip<R>(schema: &str, records: &[R], batch_size: usize, capacity: usize) -> Result<()>
+where
+    R: IntoValue<ByteArray> + FromValue<ByteArray> + Clone + PartialEq + Debug,
+{
+    let bytes = write(schema, records)?;
+
+    assert_eq!(read::<R>(bytes.clone(), batch_size, capacity)?, records);
+
+    let rewritten = rewrite_with(bytes, batch_size, capacity)?;
+
+    assert_eq!(read::<R>(rewritten, batch_size, capacity)?, records);
+
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn lists_read_back_as_written(
+        records in vec(list(), 1..100),
+        batch_size in 1..16usize,
+        capacity in 1..16usize,
+    ) {
+        round_trip(testdata::NAMES_SCHEMA, &records, batch_size, capacity).unwrap();
+    }
+
+    #[test]
+    fn null_lists_and_elements_read_back_as_written(
+        records in vec(nullable_list(), 1..100),
+        batch_size in 1..16usize,
+        capacity in 1..16usize,
+    ) {
+        round_trip(testdata::NULLABLE_NAMES_SCHEMA, &records, batch_size, capacity).unwrap();
+    }
+}
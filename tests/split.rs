@@ -0,0 +1,192 @@
+mod common;
+
+use std::{path::Path, sync::Arc};
+
+use anyhow::Result;
+use bytes::Bytes;
+use common::props;
+use parquet::{
+    data_type::{ByteArrayType, Int64Type},
+    file::{reader::FileReader, serialized_reader::SerializedFileReader},
+    schema::types::ColumnPath,
+};
+use parquet_bug::{
+    parse_schema, split, testdata, testdata::RepeatedWriter, verify, FileChunkReader,
+    RewriteOptions, SplitBy, MAX_PARTITIONS,
+};
+
+const CITIES_SCHEMA: &str = "
+message schema {
+    REQUIRED INT64 id;
+    OPTIONAL BYTE_ARRAY city (UTF8);
+}
+";
+
+/// Writes a file of [`CITIES_SCHEMA`] with a row group per entry of `row_groups`.
+fn cities_file(row_groups: &[&[(i64, Option<&str>)]]) -> Result<Bytes> {
+    let schema = Arc::new(parse_schema(CITIES_SCHEMA));
+
+    testdata::create_records_file(schema, props(), row_groups, |descr, records| {
+        let mut ids = RepeatedWriter::<Int64Type>::for_column(descr, 0);
+        let mut cities = RepeatedWriter::<ByteArrayType>::for_column(descr, 1);
+
+        for (id, city) in *records {
+            ids.push_value(*id)?;
+            cities.push_value(*city)?;
+        }
+
+        Ok(vec![Box::new(ids), Box::new(cities)])
+    })
+}
+
+fn input() -> Result<Bytes> {
+    cities_file(&[
+        &[
+            (0, Some("Oslo")),
+            (1, None),
+            (2, Some("a/b=c")),
+            (3, Some("Oslo")),
+        ],
+        &[(4, None), (5, Some("Lima")), (6, Some("Oslo"))],
+    ])
+}
+
+/// Checks that the part at `path` holds `records` and has `row_groups` row groups.
+fn check_part(path: &Path, records: &[(i64, Option<&str>)], row_groups: usize) -> Result<()> {
+    let part = FileChunkReader::open(path)?;
+    let verification = verify(cities_file(&[records])?, part.clone())?;
+
+    assert_eq!(verification.divergence, None, "{}", path.display());
+    assert_eq!(verification.records, records.len(), "{}", path.display());
+
+    let reader = SerializedFileReader::new(part)?;
+    assert_eq!(reader.num_row_groups(), row_groups, "{}", path.display());
+
+    Ok(())
+}
+
+#[test]
+fn parts_of_a_row_count_span_input_row_groups() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+
+    let report = split(
+        input()?,
+        &SplitBy::Rows(3),
+        directory.path(),
+        &RewriteOptions::new(props()),
+    )?;
+
+    let parts: Vec<_> = ["part-00000", "part-00001", "part-00002"]
+        .iter()
+        .map(|name| directory.path().join(format!("{name}.parquet")))
+        .collect();
+
+    assert_eq!(report.files, parts);
+    assert_eq!(report.rows, 7);
+    assert_eq!(report.row_groups, 4);
+
+    check_part(
+        &parts[0],
+        &[(0, Some("Oslo")), (1, None), (2, Some("a/b=c"))],
+        1,
+    )?;
+    check_part(
+        &parts[1],
+        &[(3, Some("Oslo")), (4, None), (5, Some("Lima"))],
+        2,
+    )?;
+    check_part(&parts[2], &[(6, Some("Oslo"))], 1)?;
+
+    Ok(())
+}
+
+#[test]
+fn parts_by_column_are_hive_partitions() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+
+    let mut options = RewriteOptions::new(props());
+    options.batch_size = 2;
+
+    let report = split(
+        input()?,
+        &SplitBy::Column(ColumnPath::from("city")),
+        directory.path(),
+        &options,
+    )?;
+
+    let part = |partition: &str| directory.path().join(partition).join("part-00000.parquet");
+
+    assert_eq!(
+        report.files,
+        [
+            part("city=Oslo"),
+            part("city=__HIVE_DEFAULT_PARTITION__"),
+            part("city=a%2Fb%3Dc"),
+            part("city=Lima"),
+        ]
+    );
+    assert_eq!(report.rows, 7);
+    assert_eq!(report.row_groups, 6);
+
+    check_part(
+        &part("city=Oslo"),
+        &[(0, Some("Oslo")), (3, Some("Oslo")), (6, Some("Oslo"))],
+        2,
+    )?;
+    check_part(
+        &part("city=__HIVE_DEFAULT_PARTITION__"),
+        &[(1, None), (4, None)],
+        2,
+    )?;
+    check_part(&part("city=a%2Fb%3Dc"), &[(2, Some("a/b=c"))], 1)?;
+    check_part(&part("city=Lima"), &[(5, Some("Lima"))], 1)?;
+
+    Ok(())
+}
+
+#[test]
+fn repeated_columns_and_empty_parts_are_rejected() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+    let options = RewriteOptions::new(props());
+
+    let names = testdata::create_small_parquet_file(
+        Arc::new(parse_schema(testdata::NAMES_SCHEMA)),
+        props(),
+    )?;
+    let by = SplitBy::Column(ColumnPath::new(
+        ["names", "list", "list_element"]
+            .map(str::to_owned)
+            .to_vec(),
+    ));
+
+    assert!(split(names, &by, directory.path(), &options).is_err());
+    assert!(split(input()?, &SplitBy::Rows(0), directory.path(), &options).is_err());
+    assert!(split(
+        input()?,
+        &SplitBy::Column(ColumnPath::from("country")),
+        directory.path(),
+        &options
+    )
+    .is_err());
+
+    Ok(())
+}
+
+#[test]
+fn too_many_partitions_are_rejected_before_any_is_written() -> Result<()> {
+    let directory = tempfile::tempdir()?;
+
+    let records: Vec<_> = (0..=MAX_PARTITIONS as i64).map(|id| (id, None)).collect();
+    let error = split(
+        cities_file(&[&records])?,
+        &SplitBy::Column(ColumnPath::from("id")),
+        directory.path(),
+        &RewriteOptions::new(props()),
+    )
+    .unwrap_err();
+
+    assert!(error.to_string().contains("distinct values"), "{error}");
+    assert_eq!(std::fs::read_dir(directory.path())?.count(), 0);
+
+    Ok(())
+}